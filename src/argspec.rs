@@ -0,0 +1,426 @@
+/* argspec.rs - declarative option parsing for reutils
+ * Copyright (C) 2023 Elizabeth Myers. All rights reserved.
+ * SPDX-License-Identifier: GPL-2.0-only
+ */
+
+/* Every utility used to roll its own getargs loop, its own usage string, and its own idea of
+ * what to do with an unknown flag (usually: nothing). This module lets a utility declare its
+ * options once, and takes care of the rest: unknown options, missing values, mutually exclusive
+ * options, usage lines, and --help.
+ *
+ * Utilities still drive the loop themselves, so the per-option logic stays where it belongs:
+ *
+ *     let mut opts = ArgParser::new(&SPEC, args);
+ *     while let Some(opt) = opts.next_opt()? {
+ *         match opt {
+ *             Opt::Short('n') => total = opts.value().parse()...,
+ *             ...
+ *         }
+ *     }
+ *
+ * Only options present in the spec are ever returned, and they are always returned by their
+ * short name if they have one (so --lines comes back as -n).
+ * --Elizafox
+ */
+
+use std::fmt::Write;
+use std::iter::{Map, Skip};
+use std::slice::Iter;
+
+use getargs::{Opt, Options, Positionals};
+
+use crate::err::{Error, Result};
+
+// Column help text starts at in --help output
+const HELP_INDENT: usize = 24;
+
+#[derive(Debug, Copy, Clone)]
+pub struct OptSpec {
+    pub short: Option<char>,
+    pub long: Option<&'static str>,
+    pub value: Option<&'static str>,
+    // The value can be left off, and can only be given as --long=value
+    pub optional: bool,
+    pub help: &'static str,
+}
+
+impl OptSpec {
+    #[must_use]
+    pub const fn short(short: char, help: &'static str) -> Self {
+        Self {
+            short: Some(short),
+            long: None,
+            value: None,
+            optional: false,
+            help,
+        }
+    }
+
+    #[must_use]
+    pub const fn long(long: &'static str, help: &'static str) -> Self {
+        Self {
+            short: None,
+            long: Some(long),
+            value: None,
+            optional: false,
+            help,
+        }
+    }
+
+    // Add a long alias to a short option
+    #[must_use]
+    pub const fn alias(mut self, long: &'static str) -> Self {
+        self.long = Some(long);
+        self
+    }
+
+    // Make the option take a value; name is used in usage and help output
+    #[must_use]
+    pub const fn value(mut self, name: &'static str) -> Self {
+        self.value = Some(name);
+        self
+    }
+
+    // Make the long option take a value that can be left off, as in --follow[=how]
+    #[must_use]
+    pub const fn optional_value(mut self, name: &'static str) -> Self {
+        self.value = Some(name);
+        self.optional = true;
+        self
+    }
+
+    // Whether the option's value has to be given (as the next argument, if need be)
+    #[must_use]
+    pub const fn requires_value(&self) -> bool {
+        self.value.is_some() && !self.optional
+    }
+
+    const fn opt(&self) -> Opt<&'static str> {
+        match (self.short, self.long) {
+            (Some(c), _) => Opt::Short(c),
+            (None, Some(l)) => Opt::Long(l),
+            (None, None) => panic!("option with neither a short nor a long name"),
+        }
+    }
+
+    fn synopsis(&self) -> String {
+        match (self.short, self.long, self.value) {
+            (Some(c), _, Some(_)) if self.optional => format!("-{c}"),
+            (None, Some(l), Some(v)) if self.optional => format!("--{l}[={v}]"),
+            (Some(c), _, Some(v)) => format!("-{c} {v}"),
+            (Some(c), _, None) => format!("-{c}"),
+            (None, Some(l), Some(v)) => format!("--{l}={v}"),
+            (None, Some(l), None) => format!("--{l}"),
+            (None, None, _) => unreachable!(),
+        }
+    }
+
+    // Short name, long name, or both, e.g. "-c, --bytes"
+    #[must_use]
+    pub fn names(&self) -> String {
+        match (self.short, self.long) {
+            (Some(c), Some(l)) => format!("-{c}, --{l}"),
+            (Some(c), None) => format!("-{c}"),
+            (None, Some(l)) => format!("--{l}"),
+            (None, None) => unreachable!(),
+        }
+    }
+
+    fn help_line(&self) -> String {
+        // Line long options up with the long names of short ones
+        let names = if self.short.is_some() {
+            self.names()
+        } else {
+            format!("    {}", self.names())
+        };
+
+        let names = match (self.long, self.value) {
+            (Some(_), Some(v)) if self.optional => format!("{names}[={v}]"),
+            (Some(_), Some(v)) => format!("{names}={v}"),
+            (None, Some(v)) => format!("{names} {v}"),
+            (_, None) => names,
+        };
+
+        if names.len() + 4 > HELP_INDENT {
+            format!("  {names}\n{}{}", " ".repeat(HELP_INDENT), self.help)
+        } else {
+            format!("  {names:<width$}{}", self.help, width = HELP_INDENT - 2)
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ArgSpec {
+    // One-line description of the utility
+    pub about: &'static str,
+    // Operands part of each synopsis line, e.g. "[file...]"
+    pub operands: &'static [&'static str],
+    pub options: &'static [OptSpec],
+    // Groups of short options that may not be combined
+    pub exclusive: &'static [&'static [char]],
+    // Arguments are passed through unparsed, so there's not even --help (e.g. echo)
+    pub raw_args: bool,
+}
+
+impl ArgSpec {
+    fn find_short(&self, c: char) -> Option<&'static OptSpec> {
+        self.options.iter().find(|o| o.short == Some(c))
+    }
+
+    fn find_long(&self, name: &str) -> Option<&'static OptSpec> {
+        self.options.iter().find(|o| o.long == Some(name))
+    }
+
+    #[must_use]
+    pub fn exclusive_group(&self, c: char) -> Option<&'static [char]> {
+        self.exclusive.iter().copied().find(|g| g.contains(&c))
+    }
+
+    // The --help option, which is also -h unless the utility uses that for something else
+    #[must_use]
+    pub fn help_option(&self) -> Option<OptSpec> {
+        if self.raw_args {
+            None
+        } else if self.find_short('h').is_some() {
+            Some(OptSpec::long("help", "display this help and exit"))
+        } else {
+            Some(OptSpec::short('h', "display this help and exit").alias("help"))
+        }
+    }
+
+    // Every option the parser accepts, including --help
+    #[must_use]
+    pub fn all_options(&self) -> Vec<OptSpec> {
+        self.options
+            .iter()
+            .copied()
+            .chain(self.help_option())
+            .collect()
+    }
+
+    // The options part of the synopsis, e.g. "[-c|-m] [-lw] [-n lines]"
+    fn options_synopsis(&self) -> String {
+        let mut parts = Vec::<String>::new();
+        let mut flags = String::new();
+        let mut flags_pos: Option<usize> = None;
+        let mut groups_done = Vec::<&[char]>::new();
+
+        for option in self.options {
+            if let Some(group) = option.short.and_then(|c| self.exclusive_group(c)) {
+                if !groups_done.contains(&group) {
+                    groups_done.push(group);
+                    let names: Vec<_> = group
+                        .iter()
+                        .filter_map(|c| self.find_short(*c))
+                        .map(OptSpec::synopsis)
+                        .collect();
+                    parts.push(format!("[{}]", names.join("|")));
+                }
+                continue;
+            }
+
+            match (option.short, option.requires_value()) {
+                (Some(c), false) => {
+                    if flags_pos.is_none() {
+                        flags_pos = Some(parts.len());
+                        parts.push(String::new());
+                    }
+                    flags.push(c);
+                }
+                _ => parts.push(format!("[{}]", option.synopsis())),
+            }
+        }
+
+        if let Some(pos) = flags_pos {
+            parts[pos] = format!("[-{flags}]");
+        }
+
+        parts.join(" ")
+    }
+
+    // Synopsis lines, without the utility name, e.g. "[-c|-m] [-lw] [file...]"
+    #[must_use]
+    pub fn synopsis(&self) -> Vec<String> {
+        let options = self.options_synopsis();
+        let operands: &[&str] = if self.operands.is_empty() {
+            &[""]
+        } else {
+            self.operands
+        };
+
+        operands
+            .iter()
+            .map(|operand| {
+                [options.as_str(), operand]
+                    .iter()
+                    .filter(|s| !s.is_empty())
+                    .copied()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect()
+    }
+
+    // Usage lines, suitable for printing on error
+    #[must_use]
+    pub fn usage(&self, arg0: &str) -> String {
+        self.synopsis()
+            .iter()
+            .map(|line| {
+                if line.is_empty() {
+                    format!("Usage: {arg0}")
+                } else {
+                    format!("Usage: {arg0} {line}")
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // Full --help output
+    #[must_use]
+    pub fn help(&self, arg0: &str) -> String {
+        let mut ret = self.usage(arg0);
+        let _ = write!(ret, "\n\n{}\n\nOptions:", self.about);
+        for option in self.all_options() {
+            ret.push('\n');
+            ret.push_str(&option.help_line());
+        }
+
+        ret
+    }
+}
+
+type ArgIter<'a> = Map<Skip<Iter<'a, String>>, fn(&'a String) -> &'a str>;
+
+pub struct ArgParser<'a> {
+    spec: &'static ArgSpec,
+    arg0: &'a str,
+    opts: Options<&'a str, ArgIter<'a>>,
+    value: Option<&'a str>,
+    seen: Vec<char>,
+}
+
+impl<'a> ArgParser<'a> {
+    #[must_use]
+    pub fn new(spec: &'static ArgSpec, args: &'a [String]) -> Self {
+        let iter: ArgIter<'a> = args.iter().skip(1).map(String::as_str);
+        Self {
+            spec,
+            arg0: args.first().map_or("", String::as_str),
+            opts: Options::new(iter),
+            value: None,
+            seen: Vec::new(),
+        }
+    }
+
+    // Build a usage error: the message, followed by the usage lines
+    #[must_use]
+    pub fn usage_error(&self, message: &str) -> Error {
+        Error::usage(message, &self.spec.usage(self.arg0))
+    }
+
+    fn help(&self) -> Error {
+        Error::help(self.spec.help(self.arg0))
+    }
+
+    fn check_exclusive(&mut self, c: char) -> Result {
+        if let Some(group) = self.spec.exclusive_group(c) {
+            if let Some(other) = self.seen.iter().find(|o| **o != c && group.contains(o)) {
+                return Err(self.usage_error(&format!("-{c} conflicts with -{other}")));
+            }
+        }
+
+        self.seen.push(c);
+        Ok(())
+    }
+
+    /* Get the next option, or None if there are no more.
+     *
+     * Unknown options, missing values, and conflicting options all result in an error with the
+     * usage attached. --help (and -h, if the utility doesn't use it for something else) results
+     * in an error with exit code 0, with the help as its message.
+     */
+    #[allow(clippy::missing_errors_doc)]
+    pub fn next_opt(&mut self) -> Result<Option<Opt<&'static str>>> {
+        self.value = None;
+
+        let opt = match self.opts.next_opt() {
+            Ok(Some(opt)) => opt,
+            Ok(None) => return Ok(None),
+            Err(getargs::Error::DoesNotRequireValue(opt)) => {
+                return Err(self.usage_error(&format!("option '{opt}' doesn't allow an argument")));
+            }
+            Err(e) => return Err(self.usage_error(&e.to_string())),
+        };
+
+        let spec = match opt {
+            Opt::Short(c) => match self.spec.find_short(c) {
+                Some(spec) => spec,
+                None if c == 'h' => return Err(self.help()),
+                None => return Err(self.usage_error(&format!("invalid option -- '{c}'"))),
+            },
+            Opt::Long(name) => match self.spec.find_long(name) {
+                Some(spec) => spec,
+                None if name == "help" => return Err(self.help()),
+                None => return Err(self.usage_error(&format!("unrecognized option '--{name}'"))),
+            },
+        };
+
+        if spec.optional {
+            // Short options never get one, or -fn5 would be -f with a value of "n5"
+            if let Opt::Long(_) = opt {
+                self.value = self.opts.value_opt();
+            }
+        } else if spec.value.is_some() {
+            let Ok(value) = self.opts.value() else {
+                let message = match opt {
+                    Opt::Short(c) => format!("option requires an argument -- '{c}'"),
+                    Opt::Long(l) => format!("option '--{l}' requires an argument"),
+                };
+                return Err(self.usage_error(&message));
+            };
+            self.value = Some(value);
+        }
+
+        if let Some(c) = spec.short {
+            self.check_exclusive(c)?;
+        }
+
+        Ok(Some(spec.opt()))
+    }
+
+    // For utilities with no options: handles --help and --, and rejects everything else
+    #[cfg(any(
+        feature = "basename",
+        feature = "cal",
+        feature = "dirname",
+        feature = "link",
+        feature = "sleep"
+    ))]
+    #[allow(clippy::missing_errors_doc)]
+    pub fn no_opts(&mut self) -> Result {
+        match self.next_opt()? {
+            Some(_) => unreachable!(),
+            None => Ok(()),
+        }
+    }
+
+    // Value of the option just returned by next_opt; only valid for options that take one
+    #[must_use]
+    pub fn value(&self) -> &'a str {
+        self.value
+            .expect("called ArgParser::value() on an option without a value")
+    }
+
+    // Value of the option just returned by next_opt, for options where it's optional
+    #[cfg(any(feature = "tail", feature = "tee"))]
+    #[must_use]
+    pub const fn value_opt(&self) -> Option<&'a str> {
+        self.value
+    }
+
+    pub fn positionals(&mut self) -> Positionals<'_, &'a str, ArgIter<'a>> {
+        self.opts.positionals()
+    }
+}
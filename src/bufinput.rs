@@ -3,14 +3,37 @@
  * SPDX-License-Identifier: GPL-2.0-only
  */
 
-use std::fs;
-use std::io;
+#[cfg(any(
+    feature = "cat",
+    feature = "head",
+    feature = "strings",
+    feature = "tail",
+    feature = "wc"
+))]
+mod operands;
 
+#[cfg(any(
+    feature = "cat",
+    feature = "head",
+    feature = "strings",
+    feature = "tail",
+    feature = "wc"
+))]
+pub use self::operands::Operands;
+// Only cat needs to name it
+#[cfg(feature = "cat")]
+pub use self::operands::Operand;
+
+#[cfg(any(feature = "cat", feature = "head", feature = "wc"))]
+use std::{fs, io};
+
+#[cfg(any(feature = "cat", feature = "head", feature = "wc"))]
 pub enum BufInput<'a> {
     File(io::BufReader<fs::File>),
-    Standard(io::StdinLock<'a>),
+    Standard(&'a mut dyn io::BufRead),
 }
 
+#[cfg(any(feature = "cat", feature = "head", feature = "wc"))]
 impl io::Read for BufInput<'_> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         match self {
@@ -20,6 +43,7 @@ impl io::Read for BufInput<'_> {
     }
 }
 
+#[cfg(any(feature = "cat", feature = "head", feature = "wc"))]
 impl io::BufRead for BufInput<'_> {
     fn fill_buf(&mut self) -> io::Result<&[u8]> {
         match self {
@@ -36,6 +60,7 @@ impl io::BufRead for BufInput<'_> {
     }
 }
 
+#[cfg(feature = "wc")]
 impl BufInput<'_> {
     pub const fn is_file(&self) -> bool {
         match self {
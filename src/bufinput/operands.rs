@@ -0,0 +1,221 @@
+/* bufinput/operands.rs - file operands for utilities
+ * Copyright (C) 2023 Elizabeth Myers. All rights reserved.
+ * SPDX-License-Identifier: GPL-2.0-only
+ */
+
+use std::fs;
+use std::io;
+use std::iter;
+#[cfg(feature = "wc")]
+use std::num::NonZeroUsize;
+#[cfg(feature = "wc")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "wc")]
+use std::sync::mpsc;
+#[cfg(feature = "wc")]
+use std::thread;
+use std::vec;
+
+#[cfg(any(feature = "cat", feature = "head", feature = "wc"))]
+use crate::bufinput::BufInput;
+use crate::context::Context;
+use crate::err::{Error, Result};
+
+/* File operands, for utilities that take a list of files to read.
+ *
+ * Files are opened one at a time, as they're needed, so we never hold more than one open. If one
+ * can't be opened, or something goes wrong reading it, it's reported and we move on to the next;
+ * POSIX wants the rest processed regardless, with a non-zero exit status at the end, and that's
+ * what finish() gives you. No operands at all means stdin, as does "-".
+ * --Elizafox
+ */
+pub struct Operands<'n> {
+    names: iter::Enumerate<vec::IntoIter<&'n str>>,
+    failed: bool,
+}
+
+pub struct Operand<'n> {
+    pub name: &'n str,
+    // Where it was in the list of operands, counting from 0
+    #[cfg_attr(not(feature = "tail"), allow(dead_code))] // Only tail needs it
+    pub index: usize,
+    file: Option<fs::File>,
+}
+
+impl<'n> Operands<'n> {
+    pub fn new(names: impl IntoIterator<Item = &'n str>) -> Self {
+        let mut names: Vec<&'n str> = names.into_iter().collect();
+        if names.is_empty() {
+            // If ain't nobody got me, stdin got me.
+            names.push("-");
+        }
+
+        Self {
+            names: names.into_iter().enumerate(),
+            failed: false,
+        }
+    }
+
+    // Open the next operand we can, reporting any we can't
+    pub fn open_next(&mut self, ctx: &mut Context) -> Option<Operand<'n>> {
+        while let Some((index, name)) = self.names.next() {
+            match Operand::open(index, name) {
+                Ok(operand) => return Some(operand),
+                Err(e) => self.report(ctx, &Error::io(name, e)),
+            }
+        }
+
+        None
+    }
+
+    /* Open and read all the operands with work, on as many threads as we have CPUs, handing each
+     * result to each in the order the operands were given, as it becomes ready. Anything that can't
+     * be opened or read is reported in its turn, as open_next() would. stdin can only be read from
+     * here, so it's read in its turn on this thread.
+     * --Elizafox
+     */
+    #[cfg(feature = "wc")]
+    pub fn for_each_parallel<T: Send>(
+        &mut self,
+        ctx: &mut Context,
+        work: impl Fn(&mut BufInput<'_>) -> io::Result<T> + Sync,
+        mut each: impl FnMut(&mut Context, &'n str, T) -> Result,
+    ) -> Result {
+        let names: Vec<(usize, &'n str)> = self.names.by_ref().collect();
+        let threads = thread::available_parallelism()
+            .map_or(1, NonZeroUsize::get)
+            .min(names.len());
+        let next = AtomicUsize::new(0);
+
+        thread::scope(|scope| {
+            let (tx, rx) = mpsc::channel();
+            let workers: Vec<_> = (0..threads)
+                .map(|_| {
+                    let (tx, next, names, work) = (tx.clone(), &next, &names, &work);
+                    scope.spawn(move || loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(&(index, name)) = names.get(i) else {
+                            break;
+                        };
+                        if name == "-" {
+                            continue;
+                        }
+
+                        // Never stdin, so there's nothing to lend
+                        let result = Operand::open(index, name)
+                            .and_then(|operand| work(&mut operand.input(&mut io::empty())));
+                        if tx.send((i, result)).is_err() {
+                            // Nobody's listening any more
+                            break;
+                        }
+                    })
+                })
+                .collect();
+            drop(tx);
+
+            let mut ready: Vec<Option<io::Result<T>>> = names.iter().map(|_| None).collect();
+            let mut result = Ok(());
+            for (i, &(index, name)) in names.iter().enumerate() {
+                let read = if name == "-" {
+                    Operand::open(index, name)
+                        .and_then(|operand| work(&mut operand.input(&mut *ctx.stdin)))
+                } else {
+                    while ready[i].is_none() {
+                        let Ok((j, read)) = rx.recv() else {
+                            break;
+                        };
+                        ready[j] = Some(read);
+                    }
+                    let Some(read) = ready[i].take() else {
+                        // The thread that had it is gone, and it said why on its way out
+                        result = Err(Error::new(1, format!("{name}: could not be read")));
+                        break;
+                    };
+                    read
+                };
+
+                result = match read {
+                    Ok(value) => each(ctx, label(name), value),
+                    Err(e) => {
+                        self.report(ctx, &Error::io(label(name), e));
+                        Ok(())
+                    }
+                };
+                if result.is_err() {
+                    break;
+                }
+            }
+
+            // Stop the rest; a panic was already reported, so don't have the scope raise it again
+            drop(rx);
+            for worker in workers {
+                let _ = worker.join();
+            }
+            result
+        })
+    }
+
+    // Something went wrong with an operand after it was opened
+    pub fn report(&mut self, ctx: &mut Context, error: &Error) {
+        ctx.report(error);
+        self.failed = true;
+    }
+
+    #[allow(clippy::missing_errors_doc)]
+    pub fn finish(self) -> Result {
+        if self.failed {
+            Err(Error::partial())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// What to call the operand called name in output
+fn label(name: &str) -> &str {
+    if name == "-" {
+        "stdin"
+    } else {
+        name
+    }
+}
+
+impl<'n> Operand<'n> {
+    // "-" is stdin, which is always there to be opened
+    fn open(index: usize, name: &'n str) -> io::Result<Self> {
+        let file = if name == "-" {
+            None
+        } else {
+            Some(fs::File::open(name)?)
+        };
+
+        Ok(Self { name, index, file })
+    }
+
+    // The file, if it isn't stdin
+    #[cfg(feature = "cat")]
+    pub const fn file(&self) -> Option<&fs::File> {
+        self.file.as_ref()
+    }
+
+    // The file, if it isn't stdin, for keeping hold of after reading it
+    #[cfg(any(feature = "strings", feature = "tail"))]
+    #[allow(clippy::missing_const_for_fn)] // Can't drop in a const fn
+    pub fn into_file(self) -> Option<fs::File> {
+        self.file
+    }
+
+    // What to call this operand in output
+    pub fn label(&self) -> &'n str {
+        label(self.name)
+    }
+
+    // Get something to read from; stdin is ours to lend out, since we don't own it
+    #[cfg(any(feature = "cat", feature = "head", feature = "wc"))]
+    pub fn input(self, stdin: &mut dyn io::BufRead) -> BufInput<'_> {
+        self.file.map_or_else(
+            || BufInput::Standard(stdin),
+            |f| BufInput::File(io::BufReader::new(f)),
+        )
+    }
+}
@@ -10,7 +10,7 @@ use std::io;
 pub enum BufOutput<'a> {
     File(fs::File),
     Buffer(io::BufWriter<fs::File>),
-    Standard(io::StdoutLock<'a>),
+    Standard(&'a mut dyn io::Write),
 }
 
 impl io::Write for BufOutput<'_> {
@@ -0,0 +1,224 @@
+/* completions.rs - shell completion generation for reutils
+ * Copyright (C) 2023 Elizabeth Myers. All rights reserved.
+ * SPDX-License-Identifier: GPL-2.0-only
+ */
+
+/* Like the man pages, completions come straight from the option specs in DISPATCH_TABLE, so they
+ * only ever offer what the parser accepts, for the utilities that were built in.
+ *
+ * Utilities that don't parse their arguments (echo and friends) get no completions at all.
+ * --Elizafox
+ */
+
+use std::io::{self, Write};
+
+use crate::argspec::{ArgSpec, OptSpec};
+use crate::utils::DISPATCH_TABLE;
+
+#[derive(Copy, Clone)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bash" => Some(Self::Bash),
+            "zsh" => Some(Self::Zsh),
+            "fish" => Some(Self::Fish),
+            _ => None,
+        }
+    }
+}
+
+// Utilities with options to complete
+fn utilities() -> impl Iterator<Item = (&'static str, &'static ArgSpec)> {
+    DISPATCH_TABLE
+        .into_iter()
+        .filter(|(_, (_, _, spec))| !spec.raw_args)
+        .map(|(name, (_, _, spec))| (*name, *spec))
+}
+
+fn option_names(option: &OptSpec) -> Vec<String> {
+    option
+        .short
+        .map(|c| format!("-{c}"))
+        .into_iter()
+        .chain(option.long.map(|l| format!("--{l}")))
+        .collect()
+}
+
+fn write_bash(out: &mut dyn Write) -> io::Result<()> {
+    writeln!(
+        out,
+        "# bash completions for reutils, generated by reutils --completions bash"
+    )?;
+
+    for (name, spec) in utilities() {
+        let options = spec.all_options();
+        let words: Vec<String> = options.iter().flat_map(option_names).collect();
+        let takes_value: Vec<String> = options
+            .iter()
+            .filter(|o| o.requires_value())
+            .flat_map(option_names)
+            .collect();
+
+        writeln!(out)?;
+        writeln!(out, "_reutils_{name}() {{")?;
+        writeln!(out, "    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\"")?;
+        if !takes_value.is_empty() {
+            writeln!(out, "    case \"${{COMP_WORDS[COMP_CWORD-1]}}\" in")?;
+            writeln!(out, "        {}) return ;;", takes_value.join("|"))?;
+            writeln!(out, "    esac")?;
+        }
+        writeln!(out, "    if [[ \"$cur\" == -* ]]; then")?;
+        writeln!(
+            out,
+            "        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+            words.join(" ")
+        )?;
+        if name == "reutils" {
+            let utils: Vec<_> = DISPATCH_TABLE.keys().collect();
+            writeln!(out, "    elif [[ $COMP_CWORD -eq 1 ]]; then")?;
+            writeln!(
+                out,
+                "        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+                utils.join(" ")
+            )?;
+        }
+        writeln!(out, "    fi")?;
+        writeln!(out, "}}")?;
+        writeln!(out, "complete -o default -F _reutils_{name} {name}")?;
+    }
+
+    Ok(())
+}
+
+// Escape text for use inside an _arguments spec, which is in single quotes
+fn zsh_escape(text: &str) -> String {
+    text.replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+        .replace(':', "\\:")
+}
+
+fn zsh_option(spec: &ArgSpec, option: &OptSpec) -> String {
+    // Options exclude themselves, and anything they can't be combined with
+    let mut excludes = option_names(option);
+    if let Some(c) = option.short {
+        if let Some(group) = spec.exclusive_group(c) {
+            for other in spec.options {
+                if other.short.map_or(false, |o| o != c && group.contains(&o)) {
+                    excludes.extend(option_names(other));
+                }
+            }
+        }
+    }
+
+    let names: Vec<String> = option_names(option)
+        .into_iter()
+        .map(|n| match option.value {
+            Some(_) if option.optional && n.starts_with("--") => format!("{n}=-"),
+            Some(_) if option.optional => n,
+            Some(_) if n.starts_with("--") => format!("{n}="),
+            Some(_) => format!("{n}+"),
+            None => n,
+        })
+        .collect();
+    let names = if names.len() > 1 {
+        format!("{{{}}}", names.join(","))
+    } else {
+        names.join("")
+    };
+
+    // Two colons make the value optional
+    let colons = if option.optional { "::" } else { ":" };
+    let value = option
+        .value
+        .map_or_else(String::new, |v| format!("{colons}{}: ", zsh_escape(v)));
+
+    format!(
+        "'({})'{names}'[{}]{value}'",
+        excludes.join(" "),
+        zsh_escape(option.help)
+    )
+}
+
+fn write_zsh(out: &mut dyn Write) -> io::Result<()> {
+    let names: Vec<_> = utilities().map(|(name, _)| name).collect();
+    writeln!(out, "#compdef {}", names.join(" "))?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "# zsh completions for reutils, generated by reutils --completions zsh"
+    )?;
+    writeln!(out, "# Install this as _reutils somewhere in your $fpath.")?;
+
+    for (name, spec) in utilities() {
+        writeln!(out)?;
+        writeln!(out, "_reutils_{name}() {{")?;
+        writeln!(out, "    _arguments -s -S \\")?;
+        for option in spec.all_options() {
+            writeln!(out, "        {} \\", zsh_option(spec, &option))?;
+        }
+        if name == "reutils" {
+            let utils: Vec<_> = DISPATCH_TABLE.keys().collect();
+            writeln!(out, "        '1:utility:({})' \\", utils.join(" "))?;
+        }
+        writeln!(out, "        '*:argument:_files'")?;
+        writeln!(out, "}}")?;
+    }
+
+    writeln!(out)?;
+    writeln!(out, "case $service in")?;
+    for name in names {
+        writeln!(out, "    {name}) _reutils_{name} \"$@\" ;;")?;
+    }
+    writeln!(out, "esac")
+}
+
+fn write_fish(out: &mut dyn Write) -> io::Result<()> {
+    writeln!(
+        out,
+        "# fish completions for reutils, generated by reutils --completions fish"
+    )?;
+
+    for (name, spec) in utilities() {
+        writeln!(out)?;
+        for option in spec.all_options() {
+            write!(out, "complete -c {name}")?;
+            if let Some(c) = option.short {
+                write!(out, " -s {c}")?;
+            }
+            if let Some(l) = option.long {
+                write!(out, " -l {l}")?;
+            }
+            if option.requires_value() {
+                write!(out, " -r")?;
+            }
+            writeln!(out, " -d '{}'", option.help.replace('\'', "\\'"))?;
+        }
+
+        if name == "reutils" {
+            let utils: Vec<_> = DISPATCH_TABLE.keys().collect();
+            writeln!(
+                out,
+                "complete -c reutils -n __fish_is_first_arg -a '{}'",
+                utils.join(" ")
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn write_completions(out: &mut dyn Write, shell: Shell) -> io::Result<()> {
+    match shell {
+        Shell::Bash => write_bash(out),
+        Shell::Zsh => write_zsh(out),
+        Shell::Fish => write_fish(out),
+    }
+}
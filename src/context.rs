@@ -0,0 +1,153 @@
+/* context.rs - I/O context for utilities
+ * Copyright (C) 2023 Elizabeth Myers. All rights reserved.
+ * SPDX-License-Identifier: GPL-2.0-only
+ */
+
+/* Utilities never touch the process's stdin/stdout/stderr or environment directly; they go
+ * through one of these instead. When we're running as a binary, it wraps the real streams. When
+ * we're embedded (or under test), it wraps whatever the caller gave us.
+ *
+ * Some things (ttys, spawning processes, zero-copy tricks) only make sense on the real streams, so
+ * we keep track of whether that's what we have.
+ * --Elizafox
+ */
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread;
+
+use crate::err::Error;
+
+pub type Env = HashMap<String, String>;
+
+pub struct Context<'a> {
+    pub stdin: &'a mut dyn BufRead,
+    pub stdout: &'a mut dyn Write,
+    pub stderr: &'a mut dyn Write,
+    pub env: &'a Env,
+    arg0: String,
+    process_streams: bool,
+    // The descriptor behind stdin, when there is one
+    #[cfg(unix)]
+    stdin_fd: Option<RawFd>,
+}
+
+impl<'a> Context<'a> {
+    // Context around arbitrary streams
+    #[must_use]
+    pub fn new(
+        stdin: &'a mut dyn BufRead,
+        stdout: &'a mut dyn Write,
+        stderr: &'a mut dyn Write,
+        env: &'a Env,
+    ) -> Self {
+        Self {
+            stdin,
+            stdout,
+            stderr,
+            env,
+            arg0: String::new(),
+            process_streams: false,
+            #[cfg(unix)]
+            stdin_fd: None,
+        }
+    }
+
+    // Context around the process's own streams; only the binary should use this
+    #[must_use]
+    pub fn new_process(
+        stdin: &'a mut io::StdinLock<'static>,
+        stdout: &'a mut io::StdoutLock<'static>,
+        stderr: &'a mut io::StderrLock<'static>,
+        env: &'a Env,
+    ) -> Self {
+        Self {
+            #[cfg(unix)]
+            stdin_fd: Some(stdin.as_raw_fd()),
+            stdin,
+            stdout,
+            stderr,
+            env,
+            arg0: String::new(),
+            process_streams: true,
+        }
+    }
+
+    // Whether stdin/stdout/stderr are the real fd's 0, 1, and 2
+    #[must_use]
+    pub const fn is_process(&self) -> bool {
+        self.process_streams
+    }
+
+    // The descriptor stdin reads from, if it was built from one
+    #[cfg(unix)]
+    #[must_use]
+    pub const fn stdin_fd(&self) -> Option<RawFd> {
+        self.stdin_fd
+    }
+
+    // The name the running utility was invoked as, for diagnostics
+    #[must_use]
+    pub fn arg0(&self) -> &str {
+        &self.arg0
+    }
+
+    pub fn set_arg0(&mut self, arg0: &str) {
+        self.arg0 = arg0.to_string();
+    }
+
+    // Print a diagnostic for something that went wrong, but isn't the end of the world
+    pub fn report(&mut self, error: &Error) {
+        let _ = writeln!(self.stderr, "{}: {error}", self.arg0);
+    }
+
+    #[must_use]
+    pub fn var(&self, key: &str) -> Option<&str> {
+        self.env.get(key).map(String::as_str)
+    }
+
+    /* Run a command to completion, hooked up to our streams.
+     *
+     * For the process streams, the child just inherits them. Otherwise, we have to shuffle the
+     * data around ourselves: stdin is read in full up front and fed to the child from a thread,
+     * and its output is copied out once it exits.
+     */
+    #[allow(clippy::missing_errors_doc)]
+    pub fn run_command(&mut self, cmd: &mut Command) -> io::Result<ExitStatus> {
+        if self.process_streams {
+            self.stdout.flush()?;
+            return cmd.status();
+        }
+
+        let mut input = Vec::new();
+        self.stdin.read_to_end(&mut input)?;
+
+        let mut child = cmd
+            .env_clear()
+            .envs(self.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let writer = child
+            .stdin
+            .take()
+            .map(|mut child_stdin| thread::spawn(move || child_stdin.write_all(&input)));
+
+        let output = child.wait_with_output()?;
+
+        // The child may not read all its input, which is fine
+        if let Some(writer) = writer {
+            let _ = writer.join();
+        }
+
+        self.stdout.write_all(&output.stdout)?;
+        self.stderr.write_all(&output.stderr)?;
+
+        Ok(output.status)
+    }
+}
@@ -3,37 +3,207 @@
  * SPDX-License-Identifier: GPL-2.0-only
  */
 
+/* Exit status policy. Utilities stick to this, except where POSIX says otherwise for one of them
+ * (false is always 1, nice passes the command's status through, and so on):
+ *
+ *   0        success
+ *   1        failure, including partial failure: some operands couldn't be processed, but the
+ *            rest were, and each bad one got its own diagnostic as it happened
+ *   2        usage error: bad options or operands; the usage follows the message
+ *   3-125    utility-specific failures, documented with the utility (see install.rs)
+ *   126      a command was found but couldn't be run (nice, reutils)
+ *   127      a command wasn't found (nice, reutils)
+ *
+ * Messages are printed as "argv[0]: message" by whoever ran the utility, so utilities must never
+ * add that prefix themselves. I/O errors say which path they're about where there is one, and
+ * come out as "cat: foo: No such file or directory".
+ * --Elizafox
+ */
+
+use std::error;
 use std::fmt;
+use std::io;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+    // Anything else, with a message
+    Other,
+    // Bad invocation; the message includes the usage
+    Usage,
+    // Not an error at all: --help was asked for, and the message goes to stdout
+    Help,
+    // I/O failure, on a path if we know it
+    Io,
+    // Like Io, but we weren't allowed
+    Permission,
+    // Some operands failed, and have already been reported
+    PartialFailure,
+}
 
 #[derive(Debug)]
 pub struct Error {
     pub code: i32,
+    pub kind: ErrorKind,
     pub message: Option<String>,
+    pub path: Option<String>,
+    source: Option<io::Error>,
 }
 
+// Exit code for usage errors
+pub const USAGE_CODE: i32 = 2;
+
 pub type Result<T = (), E = Error> = std::result::Result<T, E>;
 
+// io::Error's Display tacks " (os error N)" on the end, which nobody wants to see
+fn describe(e: &io::Error) -> String {
+    let text = e.to_string();
+    match (e.raw_os_error(), text.rfind(" (os error ")) {
+        (Some(_), Some(idx)) => text[..idx].to_string(),
+        _ => text,
+    }
+}
+
 impl Error {
+    #[must_use]
     pub const fn new(code: i32, message: String) -> Self {
         Self {
             code,
+            kind: ErrorKind::Other,
             message: Some(message),
+            path: None,
+            source: None,
         }
     }
 
+    // Fail without saying anything (the utility already has, or isn't supposed to)
+    #[must_use]
     pub const fn new_nomsg(code: i32) -> Self {
         Self {
             code,
+            kind: ErrorKind::Other,
+            message: None,
+            path: None,
+            source: None,
+        }
+    }
+
+    #[must_use]
+    pub fn usage(message: &str, usage: &str) -> Self {
+        Self {
+            kind: ErrorKind::Usage,
+            ..Self::new(USAGE_CODE, format!("{message}\n{usage}"))
+        }
+    }
+
+    #[must_use]
+    pub const fn help(text: String) -> Self {
+        Self {
+            code: 0,
+            kind: ErrorKind::Help,
+            message: Some(text),
+            path: None,
+            source: None,
+        }
+    }
+
+    // An I/O error on path
+    #[must_use]
+    pub fn io(path: &str, source: io::Error) -> Self {
+        Self {
+            path: Some(path.to_string()),
+            ..Self::from(source)
+        }
+    }
+
+    // Running a command failed; POSIX says 127 if it wasn't there, and 126 otherwise
+    #[must_use]
+    pub fn exec(command: &str, source: io::Error) -> Self {
+        let code = if source.kind() == io::ErrorKind::NotFound {
+            127
+        } else {
+            126
+        };
+
+        Self {
+            code,
+            ..Self::io(command, source)
+        }
+    }
+
+    // Say what we were doing when an I/O error happened, e.g. "Could not watch"
+    #[must_use]
+    pub fn context(self, message: &str) -> Self {
+        Self {
+            message: Some(message.to_string()),
+            ..self
+        }
+    }
+
+    // For utilities with their own exit codes
+    #[must_use]
+    pub fn with_code(self, code: i32) -> Self {
+        Self { code, ..self }
+    }
+
+    // Some operands failed and were reported as we went along
+    #[must_use]
+    pub const fn partial() -> Self {
+        Self {
+            code: 1,
+            kind: ErrorKind::PartialFailure,
             message: None,
+            path: None,
+            source: None,
         }
     }
+
+    // Whether there's anything to print
+    #[must_use]
+    pub const fn is_silent(&self) -> bool {
+        self.message.is_none() && self.source.is_none()
+    }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match &self.message {
-            Some(e) => write!(f, "{e}"),
-            None => write!(f, "Error code {}", self.code),
+        // What we were doing, what on, and what went wrong: "Could not watch: foo: Bad thing"
+        let parts: Vec<String> = [
+            self.message.clone(),
+            self.path.clone(),
+            self.source.as_ref().map(describe),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        if parts.is_empty() {
+            write!(f, "Error code {}", self.code)
+        } else {
+            write!(f, "{}", parts.join(": "))
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.source.as_ref().map(|e| e as _)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        let kind = if e.kind() == io::ErrorKind::PermissionDenied {
+            ErrorKind::Permission
+        } else {
+            ErrorKind::Io
+        };
+
+        Self {
+            code: 1,
+            kind,
+            message: None,
+            path: None,
+            source: Some(e),
         }
     }
 }
@@ -3,10 +3,28 @@
  * SPDX-License-Identifier: GPL-2.0-only
  */
 
+/* Everything we install is recorded in a manifest under the prefix. That's how we know what's
+ * ours when upgrading (utilities that were dropped from the build, or moved by a layout change,
+ * get their links removed) and when uninstalling.
+ *
+ * Links are also recognised without the manifest: a symlink that resolves to our binary, or (on
+ * Unix) a hard link to the same inode, is ours. Anything else is left alone unless --force is
+ * given.
+ *
+ * Hard links to a binary we've since replaced are the awkward case, since they're just regular
+ * files. Being named in the manifest isn't enough, as a package manager (or the user) may have put
+ * a real binary there since. So the manifest also has the size and a hash of the binary we
+ * installed, and a file only counts as one of our old links if its contents match.
+ * --Elizafox
+ */
+
 use std::env::{args_os, consts::EXE_SUFFIX, current_exe};
-use std::fs::{canonicalize, copy, create_dir_all};
+use std::fmt::Write as _;
+use std::fs::{self, canonicalize, copy, create_dir_all};
+use std::io::{self, Read};
 use std::path::{Path, PathBuf};
 
+use crate::context::Context;
 use crate::err::{Error, Result};
 use crate::utils::DISPATCH_TABLE;
 
@@ -19,6 +37,8 @@ enum ErrorCode {
     Copy = 5,
     #[cfg(windows)]
     Privilege = 6, // Windows only for now, reserved for Unix
+    Remove = 7,
+    Manifest = 8,
 }
 
 #[cfg(unix)]
@@ -26,6 +46,149 @@ const DEFAULT_PREFIX: &str = "/";
 #[cfg(windows)]
 const DEFAULT_PREFIX: &str = "C:\\Program Files\\reutils"; // XXX
 
+// Relative to the prefix
+const MANIFEST_PATH: &str = "var/lib/reutils/manifest";
+
+#[allow(clippy::struct_excessive_bools)] // They're all independent switches
+#[derive(Default)]
+pub struct Options {
+    // Only say what would be done
+    pub dry_run: bool,
+    // Replace files that aren't ours
+    pub force: bool,
+    // Hard links instead of symlinks
+    pub hardlinks: bool,
+    // Put everything under usr/, for systems where /bin is a symlink to /usr/bin
+    pub usr_merged: bool,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum EntryKind {
+    Binary,
+    Symlink,
+    Hardlink,
+}
+
+impl EntryKind {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Binary => "binary",
+            Self::Symlink => "symlink",
+            Self::Hardlink => "hardlink",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "binary" => Some(Self::Binary),
+            "symlink" => Some(Self::Symlink),
+            "hardlink" => Some(Self::Hardlink),
+            _ => None,
+        }
+    }
+}
+
+// Size and FNV-1a hash of a file's contents
+type Fingerprint = (u64, u64);
+
+fn fingerprint(path: &Path) -> io::Result<Fingerprint> {
+    let mut file = fs::File::open(path)?;
+    let mut buf = vec![0u8; 65536];
+    let (mut size, mut hash) = (0u64, 0xcbf2_9ce4_8422_2325u64);
+    loop {
+        let len = match file.read(&mut buf) {
+            Ok(0) => return Ok((size, hash)),
+            Ok(len) => len,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        size += len as u64;
+        for byte in &buf[..len] {
+            hash = (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+// What we installed, with paths relative to the prefix
+#[derive(Default)]
+struct Manifest {
+    entries: Vec<(EntryKind, PathBuf)>,
+    // The binary the links were made to, if we know it
+    fingerprint: Option<Fingerprint>,
+}
+
+impl Manifest {
+    fn read(prefix: &Path) -> Result<Self> {
+        let path = prefix.join(MANIFEST_PATH);
+        let data = match fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => {
+                return Err(Error::io(&path.to_string_lossy(), e)
+                    .context("Could not read manifest")
+                    .with_code(ErrorCode::Manifest as i32))
+            }
+        };
+
+        let mut manifest = Self::default();
+        for line in data
+            .lines()
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        {
+            if let Some(fingerprint) = line.strip_prefix("fingerprint ") {
+                manifest.fingerprint = fingerprint.split_once(' ').and_then(|(size, hash)| {
+                    Some((size.parse().ok()?, u64::from_str_radix(hash, 16).ok()?))
+                });
+                if manifest.fingerprint.is_some() {
+                    continue;
+                }
+            }
+
+            let entry = line
+                .split_once(' ')
+                .and_then(|(kind, p)| Some((EntryKind::parse(kind)?, PathBuf::from(p))));
+            match entry {
+                Some(entry) => manifest.entries.push(entry),
+                None => {
+                    return Err(Error::new(
+                        ErrorCode::Manifest as i32,
+                        format!("Bad line in manifest {}: {line}", path.display()),
+                    ))
+                }
+            }
+        }
+
+        Ok(manifest)
+    }
+
+    fn write(&self, prefix: &Path) -> io::Result<()> {
+        let path = prefix.join(MANIFEST_PATH);
+        create_dir_all(path.parent().unwrap())?;
+
+        let mut data = String::from("# Files installed by reutils --install, do not edit\n");
+        if let Some((size, hash)) = self.fingerprint {
+            let _ = writeln!(data, "fingerprint {size} {hash:016x}");
+        }
+        for (kind, entry_path) in &self.entries {
+            data.push_str(kind.as_str());
+            data.push(' ');
+            data.push_str(&entry_path.to_string_lossy().replace('\\', "/"));
+            data.push('\n');
+        }
+
+        fs::write(path, data)
+    }
+
+    fn contains(&self, kind: EntryKind, path: &Path) -> bool {
+        self.entries.iter().any(|(k, p)| *k == kind && p == path)
+    }
+
+    fn has_binary(&self) -> bool {
+        self.entries.iter().any(|(k, _)| *k == EntryKind::Binary)
+    }
+}
+
 #[cfg(unix)]
 fn create_file_symlink<P: AsRef<Path>, Q: AsRef<Path>>(p: &P, q: &Q) -> std::io::Result<()> {
     use std::os::unix::fs::symlink;
@@ -40,6 +203,18 @@ fn create_file_symlink<P: AsRef<Path>, Q: AsRef<Path>>(p: &P, q: &Q) -> std::io:
     Ok(())
 }
 
+#[cfg(unix)]
+fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+// Windows has no stable way to get at file ID's, so we rely on the manifest there
+#[cfg(windows)]
+const fn same_file(_a: &fs::Metadata, _b: &fs::Metadata) -> bool {
+    false
+}
+
 fn current_exe_path() -> PathBuf {
     let arg0 = args_os().next().expect("Could not get arg0");
     let name = Path::new(&arg0)
@@ -56,7 +231,176 @@ fn current_exe_path() -> PathBuf {
     canonicalize(&path).unwrap_or(path)
 }
 
-pub fn perform(prefix: &str) -> Result {
+// Where a utility goes, relative to the prefix
+fn util_path(path: &str, usr_merged: bool) -> PathBuf {
+    if usr_merged && (path.starts_with("bin/") || path.starts_with("sbin/")) {
+        Path::new("usr").join(path)
+    } else {
+        PathBuf::from(path)
+    }
+}
+
+// Is the file at path a link to our binary, of the given kind?
+fn is_our_link(path: &Path, binary: &Path, kind: EntryKind) -> bool {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return false;
+    };
+
+    match kind {
+        EntryKind::Symlink if metadata.file_type().is_symlink() => {
+            let Ok(target) = fs::read_link(path) else {
+                return false;
+            };
+            // Relative links are relative to the directory the link is in
+            let target = path
+                .parent()
+                .map_or_else(|| target.clone(), |p| p.join(&target));
+            match (canonicalize(target), canonicalize(binary)) {
+                (Ok(a), Ok(b)) => a == b,
+                _ => false,
+            }
+        }
+        EntryKind::Hardlink if metadata.file_type().is_file() => {
+            fs::metadata(binary).map_or(false, |b| same_file(&metadata, &b))
+        }
+        _ => false,
+    }
+}
+
+// Is the file at path any kind of link to our binary, now or from a previous install?
+fn is_ours(path: &Path, binary: &Path, relative: &Path, manifest: &Manifest) -> bool {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return false;
+    };
+
+    if metadata.file_type().is_symlink() {
+        is_our_link(path, binary, EntryKind::Symlink)
+    } else if metadata.file_type().is_file() {
+        // Hard links to a binary we've since replaced aren't the same file any more
+        is_our_link(path, binary, EntryKind::Hardlink)
+            || (manifest.contains(EntryKind::Hardlink, relative)
+                && manifest.fingerprint.map_or(false, |ours| {
+                    metadata.len() == ours.0 && fingerprint(path).ok() == Some(ours)
+                }))
+    } else {
+        false
+    }
+}
+
+// Something the manifest says we installed is there, but it isn't ours any more
+fn replaced(ctx: &mut Context, path: &Path) -> Result {
+    writeln!(
+        ctx.stdout,
+        "Skipping {} as it is no longer a link to reutils",
+        path.display()
+    )?;
+    Ok(())
+}
+
+fn remove(path: &Path) -> Result {
+    fs::remove_file(path).map_err(|e| {
+        Error::io(&path.to_string_lossy(), e)
+            .context("Could not remove")
+            .with_code(ErrorCode::Remove as i32)
+    })
+}
+
+// In a dry run, nothing is ever created, so remember what we said we'd create in created
+fn create_parent(
+    ctx: &mut Context,
+    path: &Path,
+    options: &Options,
+    created: &mut Vec<PathBuf>,
+) -> Result {
+    let parent = path.parent().unwrap();
+    if parent.exists() || created.iter().any(|p| p == parent) {
+        return Ok(());
+    }
+
+    writeln!(ctx.stdout, "Creating directory {}", parent.display())?;
+    if options.dry_run {
+        created.push(parent.to_path_buf());
+        return Ok(());
+    }
+
+    create_dir_all(parent).map_err(|e| {
+        Error::io(&parent.to_string_lossy(), e)
+            .context("Could not create directory")
+            .with_code(ErrorCode::CreateDir as i32)
+    })
+}
+
+fn install_binary(
+    ctx: &mut Context,
+    binary: &Path,
+    options: &Options,
+    created: &mut Vec<PathBuf>,
+) -> Result {
+    let current_exe_path = current_exe_path();
+    if current_exe_path == binary {
+        writeln!(ctx.stdout, "reutils binary located at {}", binary.display())?;
+        return Ok(());
+    }
+
+    writeln!(
+        ctx.stdout,
+        "Copying reutils binary from {} => {}",
+        current_exe_path.display(),
+        binary.display()
+    )?;
+
+    create_parent(ctx, binary, options, created)?;
+    if options.dry_run {
+        return Ok(());
+    }
+
+    /* Copy then rename, so we never write into a binary that's running (or into whatever else
+     * happens to be hard linked to it).
+     */
+    let mut temp_path = binary.as_os_str().to_os_string();
+    temp_path.push(".new");
+    copy(&current_exe_path, &temp_path)
+        .and_then(|_| fs::rename(&temp_path, binary))
+        .map_err(|e| {
+            let _ = fs::remove_file(&temp_path);
+            Error::io(&binary.to_string_lossy(), e)
+                .context("Could not copy reutils binary")
+                .with_code(ErrorCode::Copy as i32)
+        })
+}
+
+// Remove links from the old manifest that aren't in the new one
+fn remove_stale(
+    ctx: &mut Context,
+    prefix: &Path,
+    binary: &Path,
+    old_manifest: &Manifest,
+    new_manifest: &Manifest,
+    options: &Options,
+) -> Result {
+    for (kind, relative) in &old_manifest.entries {
+        if *kind == EntryKind::Binary || new_manifest.entries.iter().any(|(_, p)| p == relative) {
+            continue;
+        }
+
+        let path = prefix.join(relative);
+        if !is_ours(&path, binary, relative, old_manifest) {
+            if fs::symlink_metadata(&path).is_ok() {
+                replaced(ctx, &path)?;
+            }
+            continue;
+        }
+
+        writeln!(ctx.stdout, "Removing stale link {}", path.display())?;
+        if !options.dry_run {
+            remove(&path)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn perform(ctx: &mut Context, prefix: &str, options: &Options) -> Result {
     let prefix = if prefix.is_empty() {
         Path::new(DEFAULT_PREFIX)
     } else {
@@ -67,75 +411,177 @@ pub fn perform(prefix: &str) -> Result {
     let reutils_exe_path = prefix.join(reutils_exe_base_path);
 
     #[cfg(windows)]
-    if !is_elevated::is_elevated() {
+    if !is_elevated::is_elevated() && !options.dry_run {
         return Err(Error::new(
             ErrorCode::Privilege as i32,
             "Admin privileges are required on Windows to install".to_string(),
         ));
     }
 
-    println!("Starting installation");
+    let old_manifest = Manifest::read(prefix)?;
+    let mut manifest = Manifest::default();
+    let link_kind = if options.hardlinks {
+        EntryKind::Hardlink
+    } else {
+        EntryKind::Symlink
+    };
+
+    if options.dry_run {
+        writeln!(ctx.stdout, "Dry run, nothing will be changed")?;
+    }
+    writeln!(ctx.stdout, "Starting installation")?;
 
     // Install the binary if we must
-    let current_exe_path = current_exe_path();
-    if current_exe_path == reutils_exe_path {
-        println!(
-            "reutils binary located at {}",
-            reutils_exe_path.to_string_lossy()
-        );
-    } else {
-        println!(
-            "Copying reutils binary from {} => {}",
-            current_exe_path.to_string_lossy(),
-            reutils_exe_path.to_string_lossy()
-        );
-
-        let parent = reutils_exe_path.parent().unwrap();
-        if !parent.exists() {
-            println!("Creating prefix directory {}", parent.to_str().unwrap());
-            create_dir_all(parent).map_err(|e| {
-                Error::new(
-                    ErrorCode::CreateDir as i32,
-                    format!("Could not create prefix directory: {e}"),
-                )
+    let mut created = Vec::new();
+    install_binary(ctx, &reutils_exe_path, options, &mut created)?;
+    manifest.fingerprint = fingerprint(&current_exe_path()).ok();
+    if old_manifest.has_binary() || current_exe_path() != reutils_exe_path {
+        manifest
+            .entries
+            .push((EntryKind::Binary, PathBuf::from(reutils_exe_base_path)));
+    }
+
+    // Install the utilities
+    for (util, (util_base_path, _, _)) in &DISPATCH_TABLE {
+        if *util == "reutils" {
+            continue;
+        }
+
+        let relative = util_path(util_base_path, options.usr_merged);
+        let util_path = prefix.join(&relative);
+
+        if is_our_link(&util_path, &reutils_exe_path, link_kind) {
+            writeln!(ctx.stdout, "Skipping {util} as it is already installed")?;
+            manifest.entries.push((link_kind, relative));
+            continue;
+        }
+
+        if fs::symlink_metadata(&util_path).is_ok() {
+            if is_ours(&util_path, &reutils_exe_path, &relative, &old_manifest) {
+                writeln!(ctx.stdout, "Replacing old link {}", util_path.display())?;
+            } else if options.force {
+                writeln!(ctx.stdout, "Replacing {}", util_path.display())?;
+            } else {
+                writeln!(
+                    ctx.stdout,
+                    "Skipping {util} as {} exists (use --force to replace it)",
+                    util_path.display()
+                )?;
+                continue;
+            }
+
+            if !options.dry_run {
+                remove(&util_path)?;
+            }
+        }
+
+        writeln!(ctx.stdout, "Installing {util} => {}", util_path.display())?;
+        create_parent(ctx, &util_path, options, &mut created)?;
+
+        if !options.dry_run {
+            let result = if options.hardlinks {
+                fs::hard_link(&reutils_exe_path, &util_path)
+            } else {
+                create_file_symlink(&reutils_exe_path, &util_path)
+            };
+            result.map_err(|e| {
+                Error::io(&util_path.to_string_lossy(), e)
+                    .context("Could not create link")
+                    .with_code(ErrorCode::Symlink as i32)
             })?;
         }
 
-        copy(&current_exe_path, &reutils_exe_path).map_err(|e| {
-            Error::new(
-                ErrorCode::Copy as i32,
-                format!("Could not copy reutils binary: {e}"),
-            )
+        manifest.entries.push((link_kind, relative));
+    }
+
+    // Get rid of anything from the last install that we didn't install this time
+    remove_stale(
+        ctx,
+        prefix,
+        &reutils_exe_path,
+        &old_manifest,
+        &manifest,
+        options,
+    )?;
+
+    let manifest_path = prefix.join(MANIFEST_PATH);
+    writeln!(ctx.stdout, "Writing manifest {}", manifest_path.display())?;
+    if !options.dry_run {
+        manifest.write(prefix).map_err(|e| {
+            Error::io(&manifest_path.to_string_lossy(), e)
+                .context("Could not write manifest")
+                .with_code(ErrorCode::Manifest as i32)
         })?;
     }
 
-    // Install the utilities
-    for (util, (util_path, _)) in &DISPATCH_TABLE {
-        let util_path = prefix.join(util_path);
-        if util_path.exists() {
-            println!("Skipping {util} as it is already installed");
+    Ok(())
+}
+
+pub fn uninstall(ctx: &mut Context, prefix: &str, options: &Options) -> Result {
+    let prefix = if prefix.is_empty() {
+        Path::new(DEFAULT_PREFIX)
+    } else {
+        Path::new(prefix)
+    };
+
+    let reutils_exe_base_path = DISPATCH_TABLE.get("reutils").copied().unwrap().0;
+    let reutils_exe_path = prefix.join(reutils_exe_base_path);
+
+    let manifest = Manifest::read(prefix)?;
+
+    if options.dry_run {
+        writeln!(ctx.stdout, "Dry run, nothing will be changed")?;
+    }
+    writeln!(ctx.stdout, "Starting uninstallation")?;
+
+    // Everything in the manifest, plus wherever the utilities we know about could be
+    let mut candidates: Vec<PathBuf> = Vec::new();
+    let known = DISPATCH_TABLE
+        .into_iter()
+        .filter(|(util, _)| *util != "reutils")
+        .flat_map(|(_, (path, _, _))| [util_path(path, false), util_path(path, true)]);
+    for relative in manifest
+        .entries
+        .iter()
+        .filter(|(kind, _)| *kind != EntryKind::Binary)
+        .map(|(_, p)| p.clone())
+        .chain(known)
+    {
+        if !candidates.contains(&relative) {
+            candidates.push(relative);
+        }
+    }
+
+    for relative in candidates {
+        let path = prefix.join(&relative);
+        if !is_ours(&path, &reutils_exe_path, &relative, &manifest) {
+            let installed = manifest.entries.iter().any(|(_, p)| *p == relative);
+            if installed && fs::symlink_metadata(&path).is_ok() {
+                replaced(ctx, &path)?;
+            }
             continue;
         }
 
-        println!("Installing {util} => {}", util_path.to_str().unwrap());
+        writeln!(ctx.stdout, "Removing {}", path.display())?;
+        if !options.dry_run {
+            remove(&path)?;
+        }
+    }
 
-        let parent = util_path.parent().unwrap();
-        if !parent.exists() {
-            println!("Creating directory {}", parent.to_str().unwrap());
-            create_dir_all(parent).map_err(|e| {
-                Error::new(
-                    ErrorCode::CreateDir as i32,
-                    format!("Directory could not be created: {e}"),
-                )
-            })?;
+    // Only remove the binary if we put it there
+    if manifest.has_binary() && reutils_exe_path.exists() {
+        writeln!(ctx.stdout, "Removing {}", reutils_exe_path.display())?;
+        if !options.dry_run {
+            remove(&reutils_exe_path)?;
         }
+    }
 
-        create_file_symlink(&reutils_exe_path, &util_path).map_err(|e| {
-            Error::new(
-                ErrorCode::Symlink as i32,
-                format!("Could not create symlink: {e}"),
-            )
-        })?;
+    let manifest_path = prefix.join(MANIFEST_PATH);
+    if manifest_path.exists() {
+        writeln!(ctx.stdout, "Removing {}", manifest_path.display())?;
+        if !options.dry_run {
+            remove(&manifest_path)?;
+        }
     }
 
     Ok(())
@@ -0,0 +1,148 @@
+/* lib.rs - library interface for reutils
+ * Copyright (C) 2023 Elizabeth Myers. All rights reserved.
+ * SPDX-License-Identifier: GPL-2.0-only
+ */
+
+#![deny(clippy::all)]
+#![warn(clippy::pedantic)]
+#![warn(clippy::nursery)]
+//#![warn(clippy::cargo)] -- too many false positives
+
+/* reutils can be embedded: run() runs any utility against whatever streams and environment you
+ * hand it, and returns the exit status, without touching the process's own. The binary is just
+ * a thin wrapper around run_process().
+ * --Elizafox
+ */
+
+mod argspec;
+#[cfg(any(
+    feature = "cat",
+    feature = "head",
+    feature = "strings",
+    feature = "tail",
+    feature = "wc"
+))]
+mod bufinput;
+mod bufoutput;
+mod completions;
+mod context;
+mod err;
+mod install;
+mod man;
+mod platform;
+mod utils;
+mod version;
+
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::iter;
+use std::path::Path;
+
+pub use crate::context::{Context, Env};
+pub use crate::err::{Error, ErrorKind, Result};
+pub use crate::utils::DISPATCH_TABLE;
+
+use crate::platform::signal::allow_sigpipe;
+
+#[cfg(target_os = "windows")]
+fn get_util_name(arg0: &str) -> String {
+    if &arg0[arg0.len() - 3..] == "exe" {
+        // If the file ends in .exe, strip it off.
+        return Path::new(&arg0)
+            .file_stem()
+            .expect("Failed to get path name!")
+            .to_str()
+            .expect("Failed to get path name!")
+            .to_string();
+    }
+
+    // Use the usual implementation
+    return Path::new(&arg0)
+        .file_name()
+        .expect("Failed to get path name!")
+        .to_str()
+        .expect("Failed to get path name!")
+        .to_string();
+}
+
+#[cfg(not(target_os = "windows"))]
+fn get_util_name(arg0: &str) -> String {
+    Path::new(&arg0)
+        .file_name()
+        .expect("Failed to get path name!")
+        .to_str()
+        .expect("Failed to get path name!")
+        .to_string()
+}
+
+fn exit_status(ctx: &mut Context, result: Result) -> i32 {
+    // Make sure everything's out before we report anything
+    let result = result.and_then(|()| ctx.stdout.flush().map_err(Error::from));
+
+    match result {
+        Ok(()) => 0,
+        Err(e) if e.kind == ErrorKind::Help => {
+            let _ = writeln!(ctx.stdout, "{e}");
+            let _ = ctx.stdout.flush();
+            e.code
+        }
+        Err(e) => {
+            if !e.is_silent() {
+                ctx.report(&e);
+            }
+            e.code
+        }
+    }
+}
+
+// Look up the utility for args[0] and run it
+fn dispatch(ctx: &mut Context, util: &str, args: &[String]) -> i32 {
+    let result = DISPATCH_TABLE.run(ctx, util, args).unwrap_or_else(|| {
+        ctx.set_arg0(&args[0]);
+        Err(Error::new(1, "utility not found".to_string()))
+    });
+
+    exit_status(ctx, result)
+}
+
+/// Run the utility `name` with `args` (not including the utility name itself), reading from
+/// `stdin`, writing to `stdout` and `stderr`, with `env` as its environment.
+///
+/// Returns the exit status of the utility.
+pub fn run(
+    name: &str,
+    args: &[&str],
+    stdin: &mut dyn BufRead,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+    env: &Env,
+) -> i32 {
+    let args: Vec<String> = iter::once(name)
+        .chain(args.iter().copied())
+        .map(String::from)
+        .collect();
+
+    let mut ctx = Context::new(stdin, stdout, stderr, env);
+    dispatch(&mut ctx, name, &args)
+}
+
+/// Run the utility named by `args[0]` on the process's own streams and environment.
+///
+/// This is the entry point for the reutils binary. Returns the exit status of the utility.
+#[must_use]
+#[allow(clippy::significant_drop_tightening)] // False positive, the locks live as long as ctx
+pub fn run_process(args: &[String]) -> i32 {
+    // Rust blocks SIGPIPE by default, we have to restore it.
+    allow_sigpipe();
+
+    let env: Env = env::vars_os()
+        .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)))
+        .collect();
+
+    let mut stdin = io::stdin().lock();
+    let mut stdout = io::stdout().lock();
+    let mut stderr = io::stderr().lock();
+
+    let mut ctx = Context::new_process(&mut stdin, &mut stdout, &mut stderr, &env);
+    dispatch(&mut ctx, &get_util_name(&args[0]), args)
+}
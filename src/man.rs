@@ -0,0 +1,115 @@
+/* man.rs - man page generation for reutils
+ * Copyright (C) 2023 Elizabeth Myers. All rights reserved.
+ * SPDX-License-Identifier: GPL-2.0-only
+ */
+
+/* Man pages are generated from the same specs the option parser uses, so they can never disagree
+ * with what a utility actually accepts, and nobody has to write troff by hand.
+ * --Elizafox
+ */
+
+use std::io::{self, Write};
+
+use crate::argspec::{ArgSpec, OptSpec};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// Escape text so roff shows it as-is
+fn escape(text: &str) -> String {
+    let text = text.replace('\\', "\\e").replace('-', "\\-");
+
+    // A leading . or ' would be taken as a request
+    if text.starts_with('.') || text.starts_with('\'') {
+        format!("\\&{text}")
+    } else {
+        text
+    }
+}
+
+// The option's names in bold, and its value (if any) in italics
+fn option_tag(option: &OptSpec) -> String {
+    let names = match (option.short, option.long) {
+        (Some(c), Some(l)) => format!("\\fB\\-{c}\\fR, \\fB\\-\\-{}\\fR", escape(l)),
+        (Some(c), None) => format!("\\fB\\-{c}\\fR"),
+        (None, Some(l)) => format!("\\fB\\-\\-{}\\fR", escape(l)),
+        (None, None) => unreachable!(),
+    };
+
+    match (option.long, option.value) {
+        (Some(_), Some(v)) if option.optional => format!("{names}[=\\fI{}\\fR]", escape(v)),
+        (Some(_), Some(v)) => format!("{names}=\\fI{}\\fR", escape(v)),
+        (None, Some(v)) => format!("{names} \\fI{}\\fR", escape(v)),
+        (_, None) => names,
+    }
+}
+
+// Things in sbin are for administrators
+fn section(path: &str) -> (u8, &'static str) {
+    if path.starts_with("sbin/") || path.contains("/sbin/") {
+        (8, "System Administration")
+    } else {
+        (1, "User Commands")
+    }
+}
+
+// The one-line description for the NAME section, e.g. "print newline, word, ... for each file"
+fn name_description(about: &str) -> String {
+    let first = about
+        .split(". ")
+        .next()
+        .unwrap_or(about)
+        .trim_end_matches('.');
+    let mut chars = first.chars();
+    chars.next().map_or_else(String::new, |c| {
+        c.to_lowercase().chain(chars).collect::<String>()
+    })
+}
+
+pub fn write_man(out: &mut dyn Write, name: &str, path: &str, spec: &ArgSpec) -> io::Result<()> {
+    let (section, section_name) = section(path);
+
+    writeln!(
+        out,
+        ".TH {} {section} \"\" \"reutils {VERSION}\" \"{section_name}\"",
+        name.to_uppercase()
+    )?;
+
+    writeln!(out, ".SH NAME")?;
+    writeln!(
+        out,
+        "{} \\- {}",
+        escape(name),
+        escape(&name_description(spec.about))
+    )?;
+
+    writeln!(out, ".SH SYNOPSIS")?;
+    for (i, line) in spec.synopsis().iter().enumerate() {
+        if i > 0 {
+            writeln!(out, ".br")?;
+        }
+        writeln!(out, ".B {}", escape(name))?;
+        if !line.is_empty() {
+            writeln!(out, "{}", escape(line))?;
+        }
+    }
+
+    writeln!(out, ".SH DESCRIPTION")?;
+    writeln!(out, "{}", escape(spec.about))?;
+
+    let options = spec.all_options();
+    if !options.is_empty() {
+        writeln!(out, ".SH OPTIONS")?;
+        for option in &options {
+            writeln!(out, ".TP")?;
+            writeln!(out, "{}", option_tag(option))?;
+            writeln!(out, "{}", escape(option.help))?;
+        }
+    }
+
+    if name != "reutils" {
+        writeln!(out, ".SH SEE ALSO")?;
+        writeln!(out, ".BR reutils (8)")?;
+    }
+
+    Ok(())
+}
@@ -22,5 +22,12 @@ pub mod unix;
 #[cfg(windows)]
 pub mod windows;
 
+#[cfg(feature = "cat")]
+pub mod copy;
+#[cfg(feature = "tail")]
+pub mod fileid;
+#[cfg(feature = "df")]
 pub mod fsent;
+#[cfg(feature = "tail")]
+pub mod process;
 pub mod signal;
@@ -0,0 +1,29 @@
+/* platform/fileid.rs - file identity routines for reutils
+ * Copyright (C) 2023 Elizabeth Myers. All rights reserved.
+ * SPDX-License-Identifier: GPL-2.0-only
+ */
+
+use std::fs::Metadata;
+
+/* Something that tells files apart, even when one has replaced another under the same name. On
+ * Unix that's the device and inode. Windows has nothing like it in std, so we go by the creation
+ * time, which is good enough to spot a log being rotated.
+ * --Elizafox
+ */
+pub type FileId = (u64, u64);
+
+#[cfg(unix)]
+#[must_use]
+pub fn file_id(metadata: &Metadata) -> FileId {
+    use std::os::unix::fs::MetadataExt;
+
+    (metadata.dev(), metadata.ino())
+}
+
+#[cfg(windows)]
+#[must_use]
+pub fn file_id(metadata: &Metadata) -> FileId {
+    use std::os::windows::fs::MetadataExt;
+
+    (metadata.creation_time(), 0)
+}
@@ -9,6 +9,7 @@ pub use crate::platform::unix::fsent::*;
 #[cfg(windows)]
 pub use crate::platform::windows::fsent::*;
 
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct FilesystemEntry {
     pub filesystem_name: String,
@@ -17,10 +18,29 @@ pub struct FilesystemEntry {
     pub mount_options: String,
 }
 
+#[derive(Debug)]
+pub struct MountTable {
+    pub entries: Vec<FilesystemEntry>,
+    // Set if the entries had to come from somewhere less trustworthy than usual
+    pub warning: Option<String>,
+}
+
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct FilesystemStats {
+    // Preferred I/O size; the block counts are in units of fragment_size, not this
     pub block_size: u64,
+    pub fragment_size: u64,
     pub blocks_total: u64,
     pub blocks_free: u64,
+    // Free blocks that unprivileged users can have
     pub blocks_available: u64,
+    pub inodes_total: u64,
+    pub inodes_free: u64,
+    // Free inodes that unprivileged users can have
+    pub inodes_available: u64,
+    pub filesystem_id: u64,
+    // Longest file name allowed, in bytes
+    pub name_max: u64,
+    pub read_only: bool,
 }
@@ -0,0 +1,13 @@
+/* platform/process.rs - platform process routines for reutils
+ * Copyright (C) 2023 Elizabeth Myers. All rights reserved.
+ * SPDX-License-Identifier: GPL-2.0-only
+ */
+
+#[cfg(target_os = "linux")]
+pub use crate::platform::unix::linux::process::*;
+
+#[cfg(all(unix, not(target_os = "linux")))]
+pub use crate::platform::unix::common::process::*;
+
+#[cfg(windows)]
+pub use crate::platform::windows::process::*;
@@ -4,13 +4,14 @@
  */
 
 pub mod common;
+#[cfg(feature = "df")]
 pub mod fsent;
 
 #[cfg(target_os = "linux")]
 pub mod linux;
 
-#[cfg(target_os = "freebsd")]
+#[cfg(all(target_os = "freebsd", feature = "df"))]
 pub mod freebsd;
 
-#[cfg(target_os = "macos")]
+#[cfg(all(target_os = "macos", feature = "df"))]
 pub mod macos;
@@ -0,0 +1,40 @@
+/* platform/unix/common/process.rs - Unix process routines for reutils
+ * Copyright (C) 2023 Elizabeth Myers. All rights reserved.
+ * SPDX-License-Identifier: GPL-2.0-only
+ */
+
+use std::io;
+
+use libc::{kill, pid_t, EPERM};
+
+// Whether a process exists; if we can't signal it, it's still there, it just isn't ours
+#[must_use]
+pub fn process_exists(pid: pid_t) -> bool {
+    if unsafe { kill(pid, 0) } == 0 {
+        return true;
+    }
+
+    io::Error::last_os_error().raw_os_error() == Some(EPERM)
+}
+
+// A process we're waiting to go away
+#[cfg(not(target_os = "linux"))]
+pub struct ProcessWatch {
+    // None if it's too big to be a pid at all
+    pid: Option<pid_t>,
+}
+
+#[cfg(not(target_os = "linux"))]
+impl ProcessWatch {
+    #[must_use]
+    pub fn new(pid: u32) -> Self {
+        Self {
+            pid: pid_t::try_from(pid).ok(),
+        }
+    }
+
+    #[must_use]
+    pub fn is_alive(&self) -> bool {
+        self.pid.map_or(false, process_exists)
+    }
+}
@@ -3,8 +3,11 @@
  * SPDX-License-Identifier: GPL-2.0-only
  */
 
-use libc::{signal, SIGALRM, SIGPIPE, SIG_DFL, SIG_IGN};
+use libc::{signal, SIGPIPE, SIG_DFL};
+#[cfg(feature = "tee")]
+use libc::{SIGALRM, SIG_IGN};
 
+#[cfg(feature = "tee")]
 pub fn block_ctrlc() {
     unsafe {
         signal(SIGALRM, SIG_IGN);
@@ -16,3 +19,10 @@ pub fn allow_sigpipe() {
         signal(SIGPIPE, SIG_DFL);
     }
 }
+
+#[cfg(feature = "tee")]
+pub fn ignore_sigpipe() {
+    unsafe {
+        signal(SIGPIPE, SIG_IGN);
+    }
+}
@@ -15,7 +15,7 @@ use libc::{
     MNT_EXPORTED, MNT_FORCE, MNT_GJOURNAL, MNT_LOCAL, MNT_MULTILABEL, MNT_NFS4ACLS, MNT_NOATIME,
     MNT_NOCLUSTERR, MNT_NOCLUSTERW, MNT_NOCOVER, MNT_NOEXEC, MNT_NOSUID, MNT_NOSYMFOLLOW,
     MNT_NOWAIT, MNT_QUOTA, MNT_RDONLY, MNT_RELOAD, MNT_SNAPSHOT, MNT_SOFTDEP, MNT_SUIDDIR, MNT_SUJ,
-    MNT_SYNCHRONOUS, MNT_UNION, MNT_UNTRUSTED, MNT_UPDATE, MNT_VERIFIED,
+    MNT_SYNCHRONOUS, MNT_UNION, MNT_UNTRUSTED, MNT_UPDATE, MNT_VERIFIED, ST_RDONLY,
 };
 
 use crate::platform::fsent::*;
@@ -120,7 +120,7 @@ fn get_mount_options(fs: &statfs) -> String {
 }
 
 #[allow(clippy::missing_errors_doc)]
-pub fn get_mounted_filesystems() -> io::Result<Vec<FilesystemEntry>> {
+pub fn get_mounted_filesystems() -> io::Result<MountTable> {
     let mut result = Vec::<FilesystemEntry>::new();
 
     let mut mounts: *mut statfs = null_mut();
@@ -148,7 +148,10 @@ pub fn get_mounted_filesystems() -> io::Result<Vec<FilesystemEntry>> {
         });
     }
 
-    Ok(result)
+    Ok(MountTable {
+        entries: result,
+        warning: None,
+    })
 }
 
 #[allow(clippy::missing_errors_doc)]
@@ -164,8 +167,15 @@ pub fn get_filesystem_stats(mount_point: &str) -> io::Result<FilesystemStats> {
 
     Ok(FilesystemStats {
         block_size: fs.f_bsize.into(),
+        fragment_size: fs.f_frsize.into(),
         blocks_total: fs.f_blocks.into(),
         blocks_free: fs.f_bfree.into(),
         blocks_available: fs.f_bavail.into(),
+        inodes_total: fs.f_files.into(),
+        inodes_free: fs.f_ffree.into(),
+        inodes_available: fs.f_favail.into(),
+        filesystem_id: fs.f_fsid.into(),
+        name_max: fs.f_namemax.into(),
+        read_only: fs.f_flag & ST_RDONLY != 0,
     })
 }
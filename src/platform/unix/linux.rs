@@ -3,4 +3,9 @@
  * SPDX-License-Identifier: GPL-2.0-only
  */
 
+#[cfg(feature = "cat")]
+pub mod copy;
+#[cfg(feature = "df")]
 pub mod fsent;
+#[cfg(feature = "tail")]
+pub mod process;
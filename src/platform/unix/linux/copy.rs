@@ -0,0 +1,111 @@
+/* platform/unix/linux/copy.rs - Linux zero-copy routines for reutils
+ * Copyright (C) 2023 Elizabeth Myers. All rights reserved.
+ * SPDX-License-Identifier: GPL-2.0-only
+ */
+
+/* Linux can move data between file descriptors without it ever coming up into userspace, which
+ * makes a big difference when cat is shovelling gigabytes of logs around. Which call works depends
+ * on what stdout is:
+ *
+ *   - a regular file: copy_file_range, which can even share extents on filesystems that do that
+ *   - a pipe: splice
+ *   - anything else (terminals, sockets, devices): sendfile
+ *
+ * All of them move the input's file offset along as they go, so if the kernel turns us down
+ * partway (old kernels, odd filesystems, crossing devices), whoever called us can pick up where we
+ * left off with an ordinary copy.
+ *
+ * Only regular files are worth it. Even then, procfs and sysfs files claim to be empty, and Linux
+ * 5.3 through 5.11 took them at their word and copied nothing, returning 0 as if at end of file.
+ * So nothing at all on the first go means we let the ordinary copy have a look.
+ * --Elizafox
+ */
+
+use std::fs::File;
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::ptr;
+
+use libc::{
+    copy_file_range, fstat, sendfile, splice, EBADF, EINTR, EINVAL, ENOSYS, EOPNOTSUPP, EXDEV,
+    S_IFIFO, S_IFMT, S_IFREG,
+};
+
+const STDOUT_FD: RawFd = 1;
+
+// As much as Linux will move in one go
+const CHUNK: usize = 0x7fff_f000;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Method {
+    CopyFileRange,
+    Splice,
+    Sendfile,
+}
+
+fn stat(fd: RawFd) -> io::Result<libc::stat> {
+    let mut st = MaybeUninit::<libc::stat>::uninit();
+    if unsafe { fstat(fd, st.as_mut_ptr()) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(unsafe { st.assume_init() })
+}
+
+fn transfer(method: Method, from: RawFd) -> isize {
+    unsafe {
+        match method {
+            Method::CopyFileRange => {
+                copy_file_range(from, ptr::null_mut(), STDOUT_FD, ptr::null_mut(), CHUNK, 0)
+            }
+            Method::Splice => splice(from, ptr::null_mut(), STDOUT_FD, ptr::null_mut(), CHUNK, 0),
+            Method::Sendfile => sendfile(STDOUT_FD, from, ptr::null_mut(), CHUNK),
+        }
+    }
+}
+
+/* Copy the rest of from to the process's stdout, which must have been flushed first.
+ *
+ * Returns false if the kernel wouldn't do it (or the rest of it), or copied nothing; the caller
+ * should carry on with a normal copy in that case.
+ */
+pub fn copy_to_stdout(from: &File) -> io::Result<bool> {
+    let from = from.as_raw_fd();
+    let in_st = stat(from)?;
+    let out_st = stat(STDOUT_FD)?;
+
+    if in_st.st_mode & S_IFMT != S_IFREG {
+        return Ok(false);
+    }
+
+    // Don't try to be clever when reading stdout's own file; the caller can deal with it
+    if in_st.st_dev == out_st.st_dev && in_st.st_ino == out_st.st_ino {
+        return Ok(false);
+    }
+
+    let method = match out_st.st_mode & S_IFMT {
+        S_IFREG => Method::CopyFileRange,
+        S_IFIFO => Method::Splice,
+        _ => Method::Sendfile,
+    };
+
+    let mut copied = 0usize;
+    loop {
+        match transfer(method, from) {
+            0 => return Ok(copied > 0),
+            n if n > 0 => {
+                copied += n.unsigned_abs();
+                continue;
+            }
+            _ => {}
+        }
+
+        let e = io::Error::last_os_error();
+        match e.raw_os_error() {
+            Some(EINTR) => {}
+            Some(EINVAL | ENOSYS | EXDEV | EOPNOTSUPP | EBADF) => return Ok(false),
+            _ => return Err(e),
+        }
+    }
+}
@@ -7,13 +7,14 @@ use std::ffi::{CStr, CString};
 use std::io;
 use std::mem::MaybeUninit;
 
-use libc::{endmntent, getmntent_r, mntent, setmntent, statvfs, PATH_MAX};
+use libc::{endmntent, getmntent_r, mntent, setmntent, statvfs, PATH_MAX, ST_RDONLY};
 
-use crate::platform::fsent::{FilesystemEntry, FilesystemStats};
+use crate::platform::fsent::{FilesystemEntry, FilesystemStats, MountTable};
 
 #[allow(clippy::missing_errors_doc)]
-pub fn get_mounted_filesystems() -> io::Result<Vec<FilesystemEntry>> {
+pub fn get_mounted_filesystems() -> io::Result<MountTable> {
     let mut entries = Vec::<FilesystemEntry>::new();
+    let mut warning = None;
     let mut result: Option<io::Error> = None;
 
     /* If you're building for a REALLY old Linux, from before kernel 2.4.19:
@@ -26,21 +27,28 @@ pub fn get_mounted_filesystems() -> io::Result<Vec<FilesystemEntry>> {
      * choices.
      * --Elizafox
      */
-    let mut mntfile = unsafe { setmntent(b"/proc/self/mounts\0".as_ptr(), "r\0".as_ptr()) };
+    let mut mntfile = unsafe {
+        setmntent(
+            b"/proc/self/mounts\0".as_ptr().cast(),
+            b"r\0".as_ptr().cast(),
+        )
+    };
     if mntfile.is_null() {
         // Sigh.
-        mntfile = unsafe { setmntent(b"/etc/mtab\0".as_ptr(), "r\0".as_ptr()) };
+        mntfile = unsafe { setmntent(b"/etc/mtab\0".as_ptr().cast(), b"r\0".as_ptr().cast()) };
         if mntfile.is_null() {
             return Err(io::Error::last_os_error());
         }
 
-        eprintln!("WARNING: could not open /proc/self/mounts, falling back to /etc/mtab.");
-        eprintln!("Information may not be accurate!");
+        warning = Some(
+            "Could not open /proc/self/mounts, falling back to /etc/mtab, which may not be accurate"
+                .to_string(),
+        );
     }
 
     loop {
         let mut mnt = MaybeUninit::<mntent>::uninit();
-        let mut buf: [u8; (PATH_MAX * 4) as usize] = [0; (PATH_MAX * 4) as usize];
+        let mut buf: [libc::c_char; (PATH_MAX * 4) as usize] = [0; (PATH_MAX * 4) as usize];
 
         #[allow(clippy::cast_possible_truncation)]
         #[allow(clippy::cast_possible_wrap)]
@@ -73,7 +81,7 @@ pub fn get_mounted_filesystems() -> io::Result<Vec<FilesystemEntry>> {
         endmntent(mntfile);
     }
 
-    result.map_or_else(|| Ok(entries), Err)
+    result.map_or_else(|| Ok(MountTable { entries, warning }), Err)
 }
 
 #[allow(clippy::missing_errors_doc)]
@@ -89,8 +97,15 @@ pub fn get_filesystem_stats(mount_point: &str) -> io::Result<FilesystemStats> {
 
     Ok(FilesystemStats {
         block_size: fs.f_bsize,
+        fragment_size: fs.f_frsize,
         blocks_total: fs.f_blocks,
         blocks_free: fs.f_bfree,
         blocks_available: fs.f_bavail,
+        inodes_total: fs.f_files,
+        inodes_free: fs.f_ffree,
+        inodes_available: fs.f_favail,
+        filesystem_id: fs.f_fsid,
+        name_max: fs.f_namemax,
+        read_only: fs.f_flag & ST_RDONLY != 0,
     })
 }
@@ -0,0 +1,100 @@
+/* platform/unix/linux/process.rs - Linux process routines for reutils
+ * Copyright (C) 2023 Elizabeth Myers. All rights reserved.
+ * SPDX-License-Identifier: GPL-2.0-only
+ */
+
+/* kill(pid, 0) only says that *a* process has the pid. If ours exits and the pid gets reused, we'd
+ * wait on the new one forever. A pidfd refers to the process itself, and becomes readable when it
+ * exits, so we use one of those if the kernel has them (5.3 and up), and kill() if not.
+ * --Elizafox
+ */
+
+use std::io;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+
+use libc::{pid_t, poll, pollfd, syscall, SYS_pidfd_open, ESRCH, POLLIN};
+
+use crate::platform::unix::common::process::process_exists;
+
+// A process we're waiting to go away
+pub struct ProcessWatch {
+    pid: pid_t,
+    pidfd: Option<OwnedFd>,
+    gone: bool,
+}
+
+impl ProcessWatch {
+    #[must_use]
+    pub fn new(pid: u32) -> Self {
+        let Ok(pid) = pid_t::try_from(pid) else {
+            // Too big to be a pid at all
+            return Self {
+                pid: 0,
+                pidfd: None,
+                gone: true,
+            };
+        };
+
+        let fd = unsafe { syscall(SYS_pidfd_open, pid, 0) };
+        if fd >= 0 {
+            #[allow(clippy::cast_possible_truncation)] // It's an fd
+            let pidfd = unsafe { OwnedFd::from_raw_fd(fd as RawFd) };
+            return Self {
+                pid,
+                pidfd: Some(pidfd),
+                gone: false,
+            };
+        }
+
+        Self {
+            pid,
+            pidfd: None,
+            gone: io::Error::last_os_error().raw_os_error() == Some(ESRCH),
+        }
+    }
+
+    #[must_use]
+    pub fn is_alive(&self) -> bool {
+        if self.gone {
+            return false;
+        }
+
+        let Some(pidfd) = &self.pidfd else {
+            return process_exists(self.pid);
+        };
+
+        let mut fds = pollfd {
+            fd: pidfd.as_raw_fd(),
+            events: POLLIN,
+            revents: 0,
+        };
+        match unsafe { poll(&mut fds, 1, 0) } {
+            0 => true,
+            n if n > 0 => false,
+            // Shouldn't happen, but kill() still works
+            _ => process_exists(self.pid),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::{self, Command};
+
+    use super::ProcessWatch;
+
+    #[test]
+    fn exits() {
+        assert!(ProcessWatch::new(process::id()).is_alive());
+
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+        let watch = ProcessWatch::new(child.id());
+        assert!(watch.is_alive());
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(!watch.is_alive());
+
+        // Long gone, and reaped
+        assert!(!ProcessWatch::new(child.id()).is_alive());
+    }
+}
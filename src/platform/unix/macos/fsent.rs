@@ -5,18 +5,18 @@
 
 use std::ffi::{c_int, c_void, CStr, CString};
 use std::io;
-use std::mem::MaybeUninit;
+use std::mem::{transmute, MaybeUninit};
 use std::ptr::null_mut;
 use std::slice::from_raw_parts;
 
 use libc::{
-    free, statfs, MNT_ASYNC, MNT_AUTOMOUNTED, MNT_CPROTECT, MNT_DEFWRITE, MNT_DONTBROWSE,
-    MNT_DOVOLFS, MNT_EXPORTED, MNT_IGNORE_OWNERSHIP, MNT_JOURNALED, MNT_LOCAL, MNT_MULTILABEL,
-    MNT_NOATIME, MNT_NODEV, MNT_NOEXEC, MNT_NOSUID, MNT_NOUSERXATTR, MNT_NOWAIT, MNT_QUARANTINE,
-    MNT_QUOTA, MNT_RDONLY, MNT_ROOTFS, MNT_SNAPSHOT, MNT_SYNCHRONOUS, MNT_UNION,
+    free, pathconf, statfs, _PC_NAME_MAX, MNT_ASYNC, MNT_AUTOMOUNTED, MNT_CPROTECT, MNT_DEFWRITE,
+    MNT_DONTBROWSE, MNT_DOVOLFS, MNT_EXPORTED, MNT_IGNORE_OWNERSHIP, MNT_JOURNALED, MNT_LOCAL,
+    MNT_MULTILABEL, MNT_NOATIME, MNT_NODEV, MNT_NOEXEC, MNT_NOSUID, MNT_NOUSERXATTR, MNT_NOWAIT,
+    MNT_QUARANTINE, MNT_QUOTA, MNT_RDONLY, MNT_ROOTFS, MNT_SNAPSHOT, MNT_SYNCHRONOUS, MNT_UNION,
 };
 
-use crate::platform::fsent::{FilesystemEntry, FilesystemStats};
+use crate::platform::fsent::{FilesystemEntry, FilesystemStats, MountTable};
 
 // Not exported by libc. Sigh. --Elizafox
 extern "C" {
@@ -105,7 +105,7 @@ fn get_mount_options(fs: &statfs) -> String {
 }
 
 #[allow(clippy::missing_errors_doc)]
-pub fn get_mounted_filesystems() -> io::Result<Vec<FilesystemEntry>> {
+pub fn get_mounted_filesystems() -> io::Result<MountTable> {
     let mut result = Vec::<FilesystemEntry>::new();
 
     let mut mounts: *mut statfs = null_mut();
@@ -137,7 +137,10 @@ pub fn get_mounted_filesystems() -> io::Result<Vec<FilesystemEntry>> {
     unsafe {
         free(mounts.cast::<c_void>());
     }
-    Ok(result)
+    Ok(MountTable {
+        entries: result,
+        warning: None,
+    })
 }
 
 #[allow(clippy::missing_errors_doc)]
@@ -151,10 +154,26 @@ pub fn get_filesystem_stats(mount_point: &str) -> io::Result<FilesystemStats> {
 
     let fs = unsafe { fs.assume_init() };
 
+    /* macOS's statfs is the odd one out: f_bsize is what the block counts are in (what statvfs calls
+     * f_frsize), and f_iosize is the preferred I/O size. There's no count of inodes reserved for
+     * root, the fsid's halves are private in libc, and the name limit has to be asked for separately;
+     * if pathconf won't say, every filesystem macOS ships allows 255 bytes.
+     * --Elizafox
+     */
+    let [fsid_low, fsid_high]: [u32; 2] = unsafe { transmute(fs.f_fsid) };
+    let name_max = unsafe { pathconf(mount_point.as_ptr(), _PC_NAME_MAX) };
+
     Ok(FilesystemStats {
-        block_size: fs.f_bsize.into(),
+        block_size: u64::try_from(fs.f_iosize).unwrap_or_else(|_| fs.f_bsize.into()),
+        fragment_size: fs.f_bsize.into(),
         blocks_total: fs.f_blocks,
         blocks_free: fs.f_bfree,
         blocks_available: fs.f_bavail,
+        inodes_total: fs.f_files,
+        inodes_free: fs.f_ffree,
+        inodes_available: fs.f_ffree,
+        filesystem_id: (u64::from(fsid_high) << 32) | u64::from(fsid_low),
+        name_max: u64::try_from(name_max).unwrap_or(255),
+        read_only: fs.f_flags & (MNT_RDONLY as u32) != 0,
     })
 }
@@ -3,5 +3,8 @@
  * SPDX-License-Identifier: GPL-2.0-only
  */
 
+#[cfg(feature = "uname")]
 pub mod hostinfo;
+#[cfg(feature = "tail")]
+pub mod process;
 pub mod signal;
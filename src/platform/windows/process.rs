@@ -0,0 +1,39 @@
+/* platform/windows/process.rs - Windows process routines for reutils
+ * Copyright (C) 2023 Elizabeth Myers. All rights reserved.
+ * SPDX-License-Identifier: GPL-2.0-only
+ */
+
+use windows::Win32::Foundation::{CloseHandle, HANDLE, WAIT_TIMEOUT};
+use windows::Win32::System::Threading::{OpenProcess, WaitForSingleObject, PROCESS_SYNCHRONIZE};
+
+// A process we're waiting to go away; the handle keeps the process ID from being reused
+pub struct ProcessWatch {
+    handle: Option<HANDLE>,
+}
+
+impl ProcessWatch {
+    #[must_use]
+    pub fn new(pid: u32) -> Self {
+        // If we can't open it, it's already gone
+        let handle = unsafe { OpenProcess(PROCESS_SYNCHRONIZE, false, pid) }.ok();
+        Self { handle }
+    }
+
+    #[must_use]
+    pub fn is_alive(&self) -> bool {
+        self.handle.map_or(
+            false,
+            |h| unsafe { WaitForSingleObject(h, 0) } == WAIT_TIMEOUT,
+        )
+    }
+}
+
+impl Drop for ProcessWatch {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle {
+            unsafe {
+                CloseHandle(handle);
+            }
+        }
+    }
+}
@@ -3,10 +3,13 @@
  * SPDX-License-Identifier: GPL-2.0-only
  */
 
+#[cfg(feature = "tee")]
 use std::ptr;
 
+#[cfg(feature = "tee")]
 use windows::Win32::System::Console::{SetConsoleCtrlHandler, PHANDLER_ROUTINE};
 
+#[cfg(feature = "tee")]
 pub fn block_ctrlc() {
     SetConsoleCtrlHandler(ptr::null_ptr(), true);
 }
@@ -14,3 +17,8 @@ pub fn block_ctrlc() {
 pub const fn allow_sigpipe() {
     // No-op
 }
+
+#[cfg(feature = "tee")]
+pub const fn ignore_sigpipe() {
+    // No-op
+}
@@ -3,72 +3,161 @@
  * SPDX-License-Identifier: GPL-2.0-only
  */
 
+#[cfg(feature = "basename")]
 mod basename;
+#[cfg(feature = "cal")]
 mod cal;
+#[cfg(feature = "cat")]
 mod cat;
-#[cfg(unix)] // Not working on Windows
+#[cfg(all(unix, feature = "df"))] // Not working on Windows
 mod df;
+#[cfg(feature = "dirname")]
 mod dirname;
+#[cfg(feature = "echo")]
 mod echo;
+#[cfg(feature = "false")]
 mod false_;
+#[cfg(feature = "head")]
 mod head;
+#[cfg(any(feature = "ln", feature = "link"))]
 mod ln_link;
+#[cfg(feature = "nice")]
 mod nice;
+#[cfg(feature = "pwd")]
 mod pwd;
-#[cfg(unix)] // Not working on Windows
+#[cfg(all(unix, feature = "renice"))] // Not working on Windows
 mod renice;
 mod reutils;
+#[cfg(feature = "sleep")]
 mod sleep;
+#[cfg(feature = "strings")]
 mod strings;
+#[cfg(feature = "tail")]
 mod tail;
+#[cfg(feature = "tee")]
 mod tee;
+#[cfg(feature = "true")]
 mod true_;
+#[cfg(feature = "tty")]
 mod tty;
+#[cfg(feature = "uname")]
 mod uname;
+#[cfg(feature = "wc")]
 mod wc;
 
+use std::slice;
+
+use crate::argspec::ArgSpec;
+use crate::context::Context;
 use crate::err::Result;
 
-use phf::{phf_ordered_map, OrderedMap};
+pub type DispatchFn = fn(&mut Context, &[String]) -> Result;
+
+// Install path, entry point, and option spec (which the docs and completions are made from)
+pub type MapValue = (&'static str, DispatchFn, &'static ArgSpec);
+
+/* This used to be a phf map, but phf quietly ignores #[cfg] on entries, which is no good now that
+ * every utility can be left out of the build. A slice honours them, and with this few entries a
+ * linear search is every bit as fast.
+ * --Elizafox
+ */
+pub struct DispatchTable(&'static [(&'static str, MapValue)]);
+
+impl DispatchTable {
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&MapValue> {
+        self.0.iter().find(|(n, _)| *n == name).map(|(_, v)| v)
+    }
+
+    // Run the utility called name, if we have it; args[0] is what it was invoked as
+    pub fn run(&self, ctx: &mut Context, name: &str, args: &[String]) -> Option<Result> {
+        let (_, util, _) = self.get(name)?;
+        ctx.set_arg0(&args[0]);
+        Some(util(ctx, args))
+    }
 
-pub type DispatchFn = fn(&[String]) -> Result;
+    pub fn keys(&self) -> impl Iterator<Item = &'static str> {
+        self.0.iter().map(|(n, _)| *n)
+    }
+}
 
-type MapValue = (&'static str, DispatchFn);
+impl<'a> IntoIterator for &'a DispatchTable {
+    type Item = &'a (&'static str, MapValue);
+    type IntoIter = slice::Iter<'a, (&'static str, MapValue)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
 
 /** Utilities must be registered in this structure.
     Otherwise, reutils won't know about them!
+    Each one (except reutils) also needs a feature of the same name in Cargo.toml.
 */
-pub const DISPATCH_TABLE: OrderedMap<&'static str, MapValue> = phf_ordered_map! {
-    "basename" => ("usr/bin/basename", basename::util),
-    "cal" => ("usr/bin/cal", cal::util),
-    "cat" => ("bin/cat", cat::util),
-    #[cfg(unix)] // Broken on Windows
-    "df" => ("bin/df", df::util),
-    "dirname" => ("usr/bin/dirname", dirname::util),
-    "echo" => ("bin/echo", echo::util),
-    "false" => ("bin/false", false_::util),
-    "head" => ("usr/bin/head", head::util),
-    "link" => ("bin/link", ln_link::util_link),
-    "ln" => ("bin/ln", ln_link::util_ln),
-    "nice" => ("usr/bin/nice", nice::util),
-    "pwd" => ("bin/pwd", pwd::util),
-    #[cfg(unix)] // Not working on Windows
-    "renice" => ("usr/bin/renice", renice::util),
-    "reutils" => ("usr/sbin/reutils", reutils::util),
-    "sleep" => ("bin/sleep", sleep::util),
-    "strings" => ("usr/bin/strings", strings::util),
-    "tail" => ("usr/bin/tail", tail::util),
-    "tee" => ("usr/bin/tee", tee::util),
-    "true" => ("bin/true", true_::util),
-    "tty" => ("usr/bin/tty", tty::util),
-    "uname" => ("usr/bin/uname", uname::util),
-    "wc" => ("usr/bin/wc", wc::util),
-};
+pub const DISPATCH_TABLE: DispatchTable = DispatchTable(&[
+    #[cfg(feature = "basename")]
+    (
+        "basename",
+        ("usr/bin/basename", basename::util, &basename::SPEC),
+    ),
+    #[cfg(feature = "cal")]
+    ("cal", ("usr/bin/cal", cal::util, &cal::SPEC)),
+    #[cfg(feature = "cat")]
+    ("cat", ("bin/cat", cat::util, &cat::SPEC)),
+    #[cfg(all(unix, feature = "df"))] // Not working on Windows
+    ("df", ("bin/df", df::util, &df::SPEC)),
+    #[cfg(feature = "dirname")]
+    (
+        "dirname",
+        ("usr/bin/dirname", dirname::util, &dirname::SPEC),
+    ),
+    #[cfg(feature = "echo")]
+    ("echo", ("bin/echo", echo::util, &echo::SPEC)),
+    #[cfg(feature = "false")]
+    ("false", ("bin/false", false_::util, &false_::SPEC)),
+    #[cfg(feature = "head")]
+    ("head", ("usr/bin/head", head::util, &head::SPEC)),
+    #[cfg(feature = "link")]
+    (
+        "link",
+        ("bin/link", ln_link::util_link, &ln_link::SPEC_LINK),
+    ),
+    #[cfg(feature = "ln")]
+    ("ln", ("bin/ln", ln_link::util_ln, &ln_link::SPEC_LN)),
+    #[cfg(feature = "nice")]
+    ("nice", ("usr/bin/nice", nice::util, &nice::SPEC)),
+    #[cfg(feature = "pwd")]
+    ("pwd", ("bin/pwd", pwd::util, &pwd::SPEC)),
+    #[cfg(all(unix, feature = "renice"))] // Not working on Windows
+    ("renice", ("usr/bin/renice", renice::util, &renice::SPEC)),
+    (
+        "reutils",
+        ("usr/sbin/reutils", reutils::util, &reutils::SPEC),
+    ),
+    #[cfg(feature = "sleep")]
+    ("sleep", ("bin/sleep", sleep::util, &sleep::SPEC)),
+    #[cfg(feature = "strings")]
+    (
+        "strings",
+        ("usr/bin/strings", strings::util, &strings::SPEC),
+    ),
+    #[cfg(feature = "tail")]
+    ("tail", ("usr/bin/tail", tail::util, &tail::SPEC)),
+    #[cfg(feature = "tee")]
+    ("tee", ("usr/bin/tee", tee::util, &tee::SPEC)),
+    #[cfg(feature = "true")]
+    ("true", ("bin/true", true_::util, &true_::SPEC)),
+    #[cfg(feature = "tty")]
+    ("tty", ("usr/bin/tty", tty::util, &tty::SPEC)),
+    #[cfg(feature = "uname")]
+    ("uname", ("usr/bin/uname", uname::util, &uname::SPEC)),
+    #[cfg(feature = "wc")]
+    ("wc", ("usr/bin/wc", wc::util, &wc::SPEC)),
+]);
 
 pub fn paths() -> Vec<(&'static str, &'static str)> {
-    let mut utils: Vec<(&'static str, &'static str)> = Vec::new();
-    for (util_name, (util_path, _)) in &DISPATCH_TABLE {
-        utils.push((util_name, util_path));
-    }
-    utils
+    DISPATCH_TABLE
+        .into_iter()
+        .map(|(util_name, (util_path, _, _))| (*util_name, *util_path))
+        .collect()
 }
@@ -5,12 +5,17 @@
 
 use std::path::Path;
 
+use crate::argspec::{ArgParser, ArgSpec};
+use crate::context::Context;
 use crate::err::{Error, Result};
 
-fn usage(arg0: &str) -> Error {
-    eprintln!("Usage: {arg0} path");
-    Error::new_nomsg(1)
-}
+pub const SPEC: ArgSpec = ArgSpec {
+    about: "Strip directory from a pathname.",
+    operands: &["string"],
+    options: &[],
+    exclusive: &[],
+    raw_args: false,
+};
 
 fn basename(path: &str) -> Result<String, Error> {
     Ok(match Path::new(&path).file_name() {
@@ -22,10 +27,16 @@ fn basename(path: &str) -> Result<String, Error> {
     })
 }
 
-pub fn util(args: &[String]) -> Result {
-    let path = args.get(1).ok_or_else(|| usage(&args[0]))?;
+pub fn util(ctx: &mut Context, args: &[String]) -> Result {
+    let mut opts = ArgParser::new(&SPEC, args);
+    opts.no_opts()?;
+
+    let path = opts
+        .positionals()
+        .next()
+        .ok_or_else(|| opts.usage_error("missing operand"))?;
 
-    println!("{}", basename(path)?);
+    writeln!(ctx.stdout, "{}", basename(path)?)?;
 
     Ok(())
 }
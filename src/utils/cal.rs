@@ -9,14 +9,27 @@
  * Also, clippy has been told to shut up a lot here, probably unwise, but there's a reason.
  */
 
+use std::fmt::Write;
+use std::io;
+
 use chrono::{Datelike, Local};
 
-use crate::err::{Error, Result};
+use crate::argspec::{ArgParser, ArgSpec};
+use crate::context::Context;
+use crate::err::Result;
+
+pub const SPEC: ArgSpec = ArgSpec {
+    about: "Display a calendar.",
+    operands: &["[year [month]]"],
+    options: &[],
+    exclusive: &[],
+    raw_args: false,
+};
 
 const fn is_leap_year(year: u64) -> bool {
     if year > 1752 {
         // Gregorian
-        !(((year % 4) > 0 && (year % 100) > 0) || ((year % 400) == 0))
+        ((year % 4) == 0 && (year % 100) > 0) || ((year % 400) == 0)
     } else {
         // Julian
         (year % 4) == 0
@@ -70,7 +83,7 @@ fn get_first_weekday_of_month(month: u8, year: u64) -> u8 {
     day_of_week
 }
 
-fn push_line(vec: &mut Vec<String>, line: &mut str, do_extra_pad: bool) {
+fn push_line(vec: &mut Vec<String>, line: &str, do_extra_pad: bool) {
     // XXX we can probably eliminate this
     let mut line = line.trim_end().to_string();
     if do_extra_pad {
@@ -126,14 +139,14 @@ fn vec_month_calendar(month: u8, year: u64, print_year: bool) -> Vec<String> {
         if year == local_year && month == local_month && current_day == local_day {
             do_extra_pad = true;
             // ANSI invert
-            line.push_str(&format!("\x1b[7m{current_day:>2}\x1b[m "));
+            let _ = write!(line, "\x1b[7m{current_day:>2}\x1b[m ");
         } else {
-            line.push_str(&format!("{current_day:>2} "));
+            let _ = write!(line, "{current_day:>2} ");
         }
 
         current_day += 1;
         if current_day > days_in_month {
-            push_line(&mut ret, &mut line, do_extra_pad);
+            push_line(&mut ret, &line, do_extra_pad);
             break;
         } else if year == 1752 && month == 9 && current_day == 3 {
             /* You may be wondering why this is here.
@@ -151,7 +164,7 @@ fn vec_month_calendar(month: u8, year: u64, print_year: bool) -> Vec<String> {
         day_of_week += 1;
         if day_of_week > 6 {
             day_of_week = 0;
-            push_line(&mut ret, &mut line, do_extra_pad);
+            push_line(&mut ret, &line, do_extra_pad);
             line.clear();
         }
     }
@@ -159,24 +172,29 @@ fn vec_month_calendar(month: u8, year: u64, print_year: bool) -> Vec<String> {
     // Add blank lines so it formats correctly later
     if ret.len() < 8 {
         for _ in 0..(8 - ret.len()) {
-            ret.push(" ".repeat(20).to_string());
+            ret.push(" ".repeat(20));
         }
     }
 
     ret
 }
 
-fn print_month_calendar(month: u8, year: u64, print_year: bool) {
+fn print_month_calendar(
+    out: &mut dyn io::Write,
+    month: u8,
+    year: u64,
+    print_year: bool,
+) -> io::Result<()> {
     let v = vec_month_calendar(month, year, print_year);
     for line in v {
-        println!("{line}");
+        writeln!(out, "{line}")?;
     }
 
-    println!();
+    writeln!(out)
 }
 
-fn print_year_calendar(year: u64) {
-    println!("{year: ^60}");
+fn print_year_calendar(out: &mut dyn io::Write, year: u64) -> io::Result<()> {
+    writeln!(out, "{year: ^60}")?;
 
     // Do this in batches of 3, just like BSD
     for i in (1..12).step_by(3) {
@@ -191,70 +209,59 @@ fn print_year_calendar(year: u64) {
             .map(|((a, b), c)| (a, b, c));
 
         for (l1, l2, l3) in iter {
-            println!("{l1}  {l2}  {l3}");
+            writeln!(out, "{l1}  {l2}  {l3}")?;
         }
 
-        println!();
+        writeln!(out)?;
     }
-}
 
-fn usage(arg0: &str) -> Error {
-    eprintln!("Usage: {arg0} [year] [month]");
-    Error::new_nomsg(1)
+    Ok(())
 }
 
-pub fn util(args: &[String]) -> Result {
-    match args.len() {
-        0 => {
-            panic!("Got no argv[0]!");
-        }
-        1 => {
+pub fn util(ctx: &mut Context, args: &[String]) -> Result {
+    let mut opts = ArgParser::new(&SPEC, args);
+    opts.no_opts()?;
+
+    let positionals = opts.positionals().collect::<Vec<_>>();
+    match positionals[..] {
+        [] => {
             let now = Local::now();
             #[allow(clippy::cast_sign_loss)] // Negative years not allowed
             let local_year = now.year() as u64;
             #[allow(clippy::cast_possible_truncation)] // Months won't be over 12
             let local_month = now.month() as u8;
 
-            print_month_calendar(local_month, local_year, true);
+            print_month_calendar(ctx.stdout, local_month, local_year, true)?;
         }
-        2 => {
-            let year = args
-                .get(1)
-                .unwrap()
+        [year] => {
+            let year = year
                 .parse::<u64>()
-                .map_err(|_| usage(&args[0]))?;
+                .map_err(|_| opts.usage_error(&format!("invalid year '{year}'")))?;
 
             if year == 0 {
-                eprintln!("Only years 1 through 18446744073709551615 accepted");
-                return Err(usage(&args[0]));
+                return Err(opts.usage_error("only years 1 through 18446744073709551615 accepted"));
             }
 
-            print_year_calendar(year);
+            print_year_calendar(ctx.stdout, year)?;
         }
-        _ => {
-            let year = args
-                .get(1)
-                .unwrap()
+        [year, month, ..] => {
+            let year = year
                 .parse::<u64>()
-                .map_err(|_| usage(&args[0]))?;
+                .map_err(|_| opts.usage_error(&format!("invalid year '{year}'")))?;
 
             if year == 0 {
-                eprintln!("Only years 1 through 18446744073709551615 accepted");
-                return Err(usage(&args[0]));
+                return Err(opts.usage_error("only years 1 through 18446744073709551615 accepted"));
             }
 
-            let month = args
-                .get(2)
-                .unwrap()
+            let month = month
                 .parse::<u8>()
-                .map_err(|_| usage(&args[0]))?;
+                .map_err(|_| opts.usage_error(&format!("invalid month '{month}'")))?;
 
             if month == 0 || month > 12 {
-                eprintln!("Only months 1 through 12 accepted");
-                return Err(usage(&args[0]));
+                return Err(opts.usage_error("only months 1 through 12 accepted"));
             }
 
-            print_month_calendar(month, year, true);
+            print_month_calendar(ctx.stdout, month, year, true)?;
         }
     }
 
@@ -3,61 +3,199 @@
  * SPDX-License-Identifier: GPL-2.0-only
  */
 
-use std::fs::File;
-use std::io::{self, Write};
+use std::io::{self, BufRead, Write};
 
-use getargs::{Opt, Options};
+use getargs::Opt;
 
-use crate::bufinput::BufInput;
+use crate::argspec::{ArgParser, ArgSpec, OptSpec};
+use crate::bufinput::{Operand, Operands};
+use crate::context::Context;
 use crate::err::{Error, Result};
+use crate::platform::copy::copy_to_stdout;
 
-pub fn util(args: &[String]) -> Result {
-    let mut opts = Options::new(args.iter().skip(1).map(String::as_str));
-    while let Some(opt) = opts.next_opt().expect("argument parsing error") {
-        match opt {
-            Opt::Short('h') | Opt::Long("help") => {
-                eprintln!("Usage: {} [-u] [-h|--help] [FILE] ...", args[0]);
-                return Ok(());
-            }
-            _ => {}
-        }
+pub const SPEC: ArgSpec = ArgSpec {
+    about: "Concatenate files and print them on the standard output.",
+    operands: &["[file...]"],
+    options: &[
+        OptSpec::short('b', "number nonblank output lines").alias("number-nonblank"),
+        OptSpec::short('e', "like -v, and show a $ at the end of each line"),
+        OptSpec::short('n', "number all output lines").alias("number"),
+        OptSpec::short('s', "squeeze runs of blank lines into one").alias("squeeze-blank"),
+        OptSpec::short('t', "like -v, and show tabs as ^I"),
+        OptSpec::short('u', "unbuffered output (always on)"),
+        OptSpec::short('v', "show nonprinting characters, except tabs and newlines")
+            .alias("show-nonprinting"),
+    ],
+    exclusive: &[],
+    raw_args: false,
+};
+
+#[derive(Default)]
+#[allow(clippy::struct_excessive_bools)] // They're flags, what do you want
+struct Flags {
+    number: bool,
+    number_nonblank: bool,
+    squeeze: bool,
+    nonprinting: bool,
+    ends: bool,
+    tabs: bool,
+}
+
+impl Flags {
+    // Whether we can just copy, or have to look at every byte
+    const fn plain(&self) -> bool {
+        !(self.number || self.number_nonblank || self.squeeze || self.nonprinting)
     }
+}
 
-    let mut files: Vec<(&str, BufInput)> = Vec::new();
+/* Everything but plain copying goes through here. It works on bytes, not text, since the whole
+ * point of -v is looking at files that aren't what they should be.
+ *
+ * The state carries across files, like it does everywhere else: numbering doesn't restart, a file
+ * that doesn't end in a newline has its last line finished by the next one, and blank runs are
+ * squeezed even when they straddle two files.
+ * --Elizafox
+ */
+#[derive(Default)]
+struct Formatter {
+    flags: Flags,
+    line: u64,
+    mid_line: bool,
+    blank_run: u64,
+}
 
-    for filename in opts.positionals() {
-        if filename == "-" {
-            files.push(("stdin", BufInput::Standard(io::stdin().lock())));
+impl Formatter {
+    // ^X for control characters, ^? for DEL, and M- in front of anything with the top bit set
+    fn push_visible(buf: &mut Vec<u8>, byte: u8) {
+        let byte = if byte >= 0x80 {
+            buf.extend_from_slice(b"M-");
+            byte & 0x7f
         } else {
-            let file = File::open(filename);
-            match file {
-                Ok(file) => {
-                    files.push((filename, BufInput::File(io::BufReader::new(file))));
-                }
-                Err(e) => {
-                    return Err(Error::new(
-                        1,
-                        format!("Could not open file: {filename}: {e}"),
-                    ));
+            byte
+        };
+
+        match byte {
+            0x7f => buf.extend_from_slice(b"^?"),
+            0..=0x1f => buf.extend_from_slice(&[b'^', byte + b'@']),
+            _ => buf.push(byte),
+        }
+    }
+
+    fn format_line(&mut self, line: &[u8], buf: &mut Vec<u8>) {
+        let (content, newline) = match line.split_last() {
+            Some((b'\n', content)) => (content, true),
+            _ => (line, false),
+        };
+
+        if !self.mid_line {
+            if content.is_empty() {
+                self.blank_run += 1;
+                if self.flags.squeeze && self.blank_run > 1 {
+                    return;
                 }
+            } else {
+                self.blank_run = 0;
+            }
+
+            let numbered = if self.flags.number_nonblank {
+                !content.is_empty()
+            } else {
+                self.flags.number
+            };
+            if numbered {
+                self.line += 1;
+                let _ = write!(buf, "{:6}\t", self.line);
+            }
+        }
+
+        for &byte in content {
+            match byte {
+                b'\t' if !self.flags.tabs => buf.push(byte),
+                _ if self.flags.nonprinting => Self::push_visible(buf, byte),
+                _ => buf.push(byte),
+            }
+        }
+
+        if newline {
+            if self.flags.ends {
+                buf.push(b'$');
+            }
+            buf.push(b'\n');
+        }
+        self.mid_line = !newline;
+    }
+
+    fn copy(&mut self, input: &mut dyn BufRead, out: &mut dyn Write) -> io::Result<()> {
+        let mut line = Vec::new();
+        let mut buf = Vec::new();
+        loop {
+            line.clear();
+            if input.read_until(b'\n', &mut line)? == 0 {
+                return Ok(());
             }
+
+            buf.clear();
+            self.format_line(&line, &mut buf);
+            out.write_all(&buf)?;
         }
     }
+}
 
-    if files.is_empty() {
-        // If ain't nobody got me, stdin got me.
-        files.push(("stdin", BufInput::Standard(io::stdin().lock())));
+// Have the kernel copy straight from the file to stdout, if it can; false if it didn't
+fn fast_copy(ctx: &mut Context, operand: &Operand) -> io::Result<bool> {
+    // Only the real stdout has a file descriptor to copy to
+    match operand.file() {
+        Some(file) if ctx.is_process() => {
+            ctx.stdout.flush()?;
+            copy_to_stdout(file)
+        }
+        _ => Ok(false),
     }
+}
 
-    for (filename, mut file) in files {
-        if let Err(e) = io::copy(&mut file, &mut io::stdout()) {
-            return Err(Error::new(1, format!("Could not write to {filename}: {e}")));
+pub fn util(ctx: &mut Context, args: &[String]) -> Result {
+    let mut flags = Flags::default();
+
+    let mut opts = ArgParser::new(&SPEC, args);
+    while let Some(opt) = opts.next_opt()? {
+        match opt {
+            Opt::Short('b') => flags.number_nonblank = true,
+            Opt::Short('e') => (flags.nonprinting, flags.ends) = (true, true),
+            Opt::Short('n') => flags.number = true,
+            Opt::Short('s') => flags.squeeze = true,
+            Opt::Short('t') => (flags.nonprinting, flags.tabs) = (true, true),
+            Opt::Short('u') => {} // We never buffer output anyway
+            Opt::Short('v') => flags.nonprinting = true,
+            _ => unreachable!(),
         }
     }
 
-    if let Err(e) = io::stdout().flush() {
-        return Err(Error::new(1, format!("Could not write to stdout: {e}")));
+    let plain = flags.plain();
+    let mut formatter = Formatter {
+        flags,
+        ..Formatter::default()
+    };
+
+    let mut operands = Operands::new(opts.positionals());
+    while let Some(operand) = operands.open_next(ctx) {
+        let label = operand.label();
+        let result = if plain {
+            fast_copy(ctx, &operand).and_then(|done| {
+                if done {
+                    Ok(())
+                } else {
+                    io::copy(&mut operand.input(ctx.stdin), ctx.stdout).map(|_| ())
+                }
+            })
+        } else {
+            formatter.copy(&mut operand.input(ctx.stdin), ctx.stdout)
+        };
+        if let Err(e) = result {
+            operands.report(ctx, &Error::io(label, e));
+        }
     }
 
-    Ok(())
+    ctx.stdout.flush().map_err(|e| Error::io("stdout", e))?;
+
+    operands.finish()
 }
@@ -3,152 +3,401 @@
  * SPDX-License-Identifier: GPL-2.0-only
  */
 
-use getargs::{Opt, Options};
+use std::io::{self, Write};
 
+use getargs::Opt;
+
+use crate::argspec::{ArgParser, ArgSpec, OptSpec};
+use crate::context::Context;
 use crate::err::{Error, Result};
 use crate::platform::fsent::{get_filesystem_stats, get_mounted_filesystems, get_path_mountpoint};
 
-fn display_table(table: Vec<[String; 6]>) {
-    let mut col_lengths: [usize; 5] = [0; 5];
+pub const SPEC: ArgSpec = ArgSpec {
+    about: "Report free disk space on mounted filesystems.",
+    operands: &["[file...]"],
+    options: &[
+        OptSpec::short('B', "use blocks of size bytes")
+            .alias("block-size")
+            .value("size"),
+        OptSpec::short('b', "use 512-byte blocks"),
+        OptSpec::short('P', "use 512-byte blocks"),
+        OptSpec::short('g', "use 1073741824-byte blocks"),
+        OptSpec::short('H', "print sizes in powers of 1000 (e.g. 1.1G)").alias("si"),
+        OptSpec::short('h', "print sizes in powers of 1024 (e.g. 1.0G)").alias("human-readable"),
+        OptSpec::short('i', "report inode usage instead of block usage").alias("inodes"),
+        OptSpec::short('k', "use 1024-byte blocks"),
+        OptSpec::short('m', "use 1048576-byte blocks"),
+        OptSpec::short('t', "include total allocated space (always on)"),
+        OptSpec::long(
+            "total",
+            "print a row with the totals of all the filesystems shown",
+        ),
+    ],
+    exclusive: &[],
+    raw_args: false,
+};
 
-    // Find the maximum length to pad
-    for row in &table {
-        for i in 0..5 {
-            if row[i].len() > col_lengths[i] {
-                col_lengths[i] = row[i].len();
-            }
+// How sizes are shown
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Units {
+    // A count of blocks of this many bytes
+    Blocks(u64),
+    // Scaled to a suffixed unit, in powers of this (1024 or 1000)
+    Human(u64),
+}
+
+impl Units {
+    fn header(self) -> String {
+        match self {
+            Self::Blocks(size) => format!("{size}-blocks"),
+            Self::Human(_) => "Size".to_string(),
         }
     }
 
-    // XXX - HACK ALERT!!!
-    for row in table {
-        println!(
-            "{} {}{}{} {}{} {}{} {}{} {}",
-            row[0],
-            " ".repeat(col_lengths[0] - row[0].len()),
-            " ".repeat(col_lengths[1] - row[1].len()),
-            row[1],
-            " ".repeat(col_lengths[2] - row[2].len()),
-            row[2],
-            " ".repeat(col_lengths[3] - row[3].len()),
-            row[3],
-            " ".repeat(col_lengths[4] - row[4].len()),
-            row[4],
-            row[5]
-        );
+    fn format(self, bytes: u64) -> String {
+        match self {
+            Self::Blocks(size) => (bytes / size).to_string(),
+            Self::Human(base) => human(bytes, base),
+        }
+    }
+
+    // Inodes are counted one at a time, whatever the block size; only -h and -H scale them
+    fn format_count(self, count: u64) -> String {
+        match self {
+            Self::Blocks(_) => count.to_string(),
+            Self::Human(base) => human(count, base),
+        }
     }
 }
 
-pub fn util(args: &[String]) -> Result {
-    let mut block_size = 4096u64; // Modern default
+fn div_ceil(n: u128, d: u128) -> u128 {
+    n / d + u128::from(n % d != 0)
+}
 
-    let mut opts = Options::new(args.iter().skip(1).map(String::as_str));
-    while let Some(opt) = opts.next_opt().expect("argument parsing error") {
-        match opt {
-            Opt::Short('b' | 'P') => block_size = 512u64,
-            Opt::Short('k') => block_size = 1024u64,
-            Opt::Short('m') => block_size = 1_048_576_u64,
-            Opt::Short('g') => block_size = 1_073_741_824_u64,
-            Opt::Short('B') | Opt::Long("block-size") => {
-                opts.value().map_or_else(
-                    |_| eprintln!("Error: No block size specified, ignoring"),
-                    |string| {
-                        string.parse::<u64>().map_or_else(
-                            |_| eprintln!("Error: Invalid block size specified, ignoring"),
-                            |value| {
-                                if value > 0 {
-                                    block_size = value;
-                                } else {
-                                    eprintln!("Error: Block size cannot be zero, ignoring");
-                                }
-                            },
-                        );
-                    },
-                );
+/* Human-readable sizes are done the way coreutils does them, since people compare the two: scale
+ * to the biggest unit that leaves less than the base, always round up (better to overstate what's
+ * used than understate it), and show a decimal place below 10. Rounding can carry into the next
+ * unit up, e.g. 1023.9K is 1.0M, not 1024K. Powers of 1000 get a lowercase k, as in SI.
+ * --Elizafox
+ */
+fn human(bytes: u64, base: u64) -> String {
+    const SUFFIXES: &[char] = &['K', 'M', 'G', 'T', 'P', 'E']; // As far as a u64 goes
+
+    if bytes < base {
+        return bytes.to_string();
+    }
+
+    let suffix = |exponent: usize| match SUFFIXES[exponent - 1] {
+        'K' if base == 1000 => 'k',
+        c => c,
+    };
+
+    let (bytes, base) = (u128::from(bytes), u128::from(base));
+    let mut exponent = 0;
+    let mut unit = 1u128;
+    while bytes / unit >= base && exponent < SUFFIXES.len() {
+        unit *= base;
+        exponent += 1;
+    }
+
+    if bytes < 10 * unit {
+        let tenths = div_ceil(bytes * 10, unit);
+        return if tenths < 100 {
+            format!("{}.{}{}", tenths / 10, tenths % 10, suffix(exponent))
+        } else {
+            format!("10{}", suffix(exponent))
+        };
+    }
+
+    let amount = div_ceil(bytes, unit);
+    if amount == base && exponent < SUFFIXES.len() {
+        format!("1.0{}", suffix(exponent + 1))
+    } else {
+        format!("{amount}{}", suffix(exponent))
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Align {
+    Left,
+    Right,
+}
+
+const COLUMNS: [Align; 6] = [
+    Align::Left,
+    Align::Right,
+    Align::Right,
+    Align::Right,
+    Align::Right,
+    Align::Left,
+];
+
+// Each column is as wide as its widest cell, with a space between them; the last column isn't
+// padded, so lines don't end in spaces
+fn display_table(out: &mut dyn Write, table: &[[String; 6]]) -> io::Result<()> {
+    let mut widths = [0usize; 6];
+    for row in table {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    for row in table {
+        let mut line = String::new();
+        for (i, (cell, align)) in row.iter().zip(COLUMNS).enumerate() {
+            let padding = " ".repeat(widths[i] - cell.chars().count());
+            if i > 0 {
+                line.push(' ');
             }
-            Opt::Short('h') | Opt::Long("help") => {
-                eprintln!(
-                    "Usage: {} [-B|--block-size] [-b|-P] [-g] [-k] [-m] [-t]",
-                    args[0]
-                );
-                return Ok(());
+
+            match align {
+                Align::Left if i == row.len() - 1 => line.push_str(cell),
+                Align::Left => {
+                    line.push_str(cell);
+                    line.push_str(&padding);
+                }
+                Align::Right => {
+                    line.push_str(&padding);
+                    line.push_str(cell);
+                }
             }
-            _ => {}
         }
-    }
 
-    let mut args = Vec::<String>::new();
-    for arg in opts.positionals() {
-        let arg = get_path_mountpoint(arg)
-            .map_err(|e| Error::new(1, format!("Could not get path: {e}")))?;
-        args.push(arg);
+        writeln!(out, "{line}")?;
     }
-    let filesystems = if args.is_empty() {
-        get_mounted_filesystems()
-            .map_err(|e| Error::new(1, format!("Could not get mounted filesystems: {e}")))?
-            .into_iter()
-            .map(|fs| (fs.mount_point, fs.mount_from))
-            .collect::<Vec<(String, String)>>()
+
+    Ok(())
+}
+
+/* Percentage in use, rounded up as POSIX requires. It's out of what's used plus what's available,
+ * not the total, since space reserved for root isn't available to anyone else; a filesystem with
+ * reserved space is full when available space runs out, and should say 100%. Filesystems with no
+ * space at all (proc, sysfs, and friends) have no percentage.
+ * --Elizafox
+ */
+fn capacity(used: u64, available: u64) -> Option<u128> {
+    // u128 to avoid overflow, an unlikely scenario but better safe than sorry
+    let total = u128::from(used) + u128::from(available);
+    (total > 0).then(|| div_ceil(100 * u128::from(used), total))
+}
+
+fn header(units: Units, inodes: bool) -> [String; 6] {
+    let columns = if inodes {
+        ["Inodes", "IUsed", "IFree", "IUse%"].map(String::from)
     } else {
-        get_mounted_filesystems()
-            .map_err(|e| Error::new(1, format!("Could not get mounted filesystems: {e}")))?
-            .into_iter()
-            .filter(|fs| args.contains(&fs.mount_point))
-            .map(|fs| (fs.mount_point, fs.mount_from))
-            .collect::<Vec<(String, String)>>()
+        [
+            units.header(),
+            "Used".into(),
+            "Available".into(),
+            "Capacity".into(),
+        ]
+    };
+
+    let [size, used, free, usage] = columns;
+    [
+        "Filesystem".into(),
+        size,
+        used,
+        free,
+        usage,
+        "Mounted on".into(),
+    ]
+}
+
+// Sizeless filesystems get a dash; so do those (btrfs, vfat) with no fixed number of inodes, which
+// report none at all, and that isn't the same as having run out
+fn usage(used: u64, available: u64) -> String {
+    capacity(used, available).map_or_else(|| "-".to_string(), |percent| format!("{percent}%"))
+}
+
+// Mount points and what's mounted on them, for the filesystems holding the given files (or all of
+// them, if there are none)
+fn filesystems<'a>(
+    ctx: &mut Context,
+    files: impl Iterator<Item = &'a str>,
+) -> Result<Vec<(String, String)>> {
+    let mut mount_points = Vec::<String>::new();
+    for file in files {
+        mount_points.push(get_path_mountpoint(file).map_err(|e| Error::io(file, e))?);
+    }
+
+    let mounts = get_mounted_filesystems()
+        .map_err(|e| Error::from(e).context("Could not get mounted filesystems"))?;
+    if let Some(warning) = mounts.warning {
+        ctx.report(&Error::new(1, warning));
+    }
+
+    Ok(mounts
+        .entries
+        .into_iter()
+        .filter(|fs| mount_points.is_empty() || mount_points.contains(&fs.mount_point))
+        .map(|fs| (fs.mount_point, fs.mount_from))
+        .collect())
+}
+
+pub fn util(ctx: &mut Context, args: &[String]) -> Result {
+    let mut units = Units::Blocks(4096); // Modern default
+    let mut total = false;
+    let mut inodes = false;
+
+    let mut opts = ArgParser::new(&SPEC, args);
+    while let Some(opt) = opts.next_opt()? {
+        match opt {
+            Opt::Short('b' | 'P') => units = Units::Blocks(512),
+            Opt::Short('k') => units = Units::Blocks(1024),
+            Opt::Short('m') => units = Units::Blocks(1_048_576),
+            Opt::Short('g') => units = Units::Blocks(1_073_741_824),
+            Opt::Short('h') => units = Units::Human(1024),
+            Opt::Short('H') => units = Units::Human(1000),
+            Opt::Short('i') => inodes = true,
+            Opt::Short('t') => {} // Always displayed
+            Opt::Long("total") => total = true,
+            Opt::Short('B') => match opts.value().parse::<u64>() {
+                Ok(0) | Err(_) => {
+                    return Err(
+                        opts.usage_error(&format!("invalid block size: '{}'", opts.value()))
+                    );
+                }
+                Ok(value) => units = Units::Blocks(value),
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    let filesystems = filesystems(ctx, opts.positionals())?;
+
+    let cell = |amount: u64| {
+        if inodes {
+            units.format_count(amount)
+        } else {
+            units.format(amount)
+        }
     };
 
-    let mut table: Vec<[String; 6]> = vec![[
-        "Filesystem".to_string(),
-        format!("{block_size}-blocks"),
-        "Used".to_string(),
-        "Available".to_string(),
-        "Capacity".to_string(),
-        "Mounted on".to_string(),
-    ]];
+    let mut failed = false;
+    let mut table = vec![header(units, inodes)];
+    let mut sums = [0u64; 3];
     for (mount_point, mount_from) in filesystems {
         let stats = match get_filesystem_stats(&mount_point) {
             Ok(s) => s,
             Err(e) => {
-                eprintln!("Could not get filesystem info for {mount_point}: {e}");
+                ctx.report(&Error::io(&mount_point, e));
+                failed = true;
                 continue;
             }
         };
 
-        let (blocks_total, blocks_free) = if block_size == stats.block_size {
-            (stats.blocks_total, stats.blocks_free)
+        // Size, used, and available. There are no inodes held back for root, as far as statvfs will
+        // tell us, so all the free ones are available.
+        let (counts, usage) = if inodes {
+            let used = stats.inodes_total.saturating_sub(stats.inodes_free);
+            let counts = [stats.inodes_total, used, stats.inodes_free];
+            (counts, usage(used, stats.inodes_free))
         } else {
-            (
-                (stats.blocks_total * stats.block_size) / block_size,
-                (stats.blocks_free * stats.block_size) / block_size,
-            )
-        };
+            // Block counts are in fragments, which needn't be the filesystem's block size
+            let used = stats.blocks_total.saturating_sub(stats.blocks_free);
+            let counts = [stats.blocks_total, used, stats.blocks_available]
+                .map(|blocks| blocks.saturating_mul(stats.fragment_size));
 
-        // Use the original values to avoid rounding error
-        #[allow(clippy::cast_possible_truncation)]
-        let capacity = if stats.blocks_total > 0 && stats.blocks_free > 0 {
-            // Use i128 to avoid overflow, an unlikely scenario but better safe than sorry.
-            let calc_blocks_used = i128::from(stats.blocks_total) - i128::from(stats.blocks_free);
-            let calc_blocks_total = i128::from(stats.blocks_total);
-            ((100i128 * calc_blocks_used) / calc_blocks_total) as i64
-                + i64::from(calc_blocks_used % calc_blocks_total != 0)
-        } else {
-            100i64
+            // Use the original values to avoid rounding error
+            (counts, usage(used, stats.blocks_available))
         };
+        for (sum, count) in sums.iter_mut().zip(counts) {
+            *sum = sum.saturating_add(count);
+        }
 
-        let blocks_used = blocks_total - blocks_free;
+        let [size, used, available] = counts.map(cell);
+        table.push([mount_from, size, used, available, usage, mount_point]);
+    }
 
+    if total {
+        let [size, used, available] = sums.map(cell);
+        let usage = usage(sums[1], sums[2]);
         table.push([
-            mount_from,
-            blocks_total.to_string(),
-            blocks_used.to_string(),
-            blocks_free.to_string(),
-            format!("{capacity}%"),
-            mount_point,
+            "total".to_string(),
+            size,
+            used,
+            available,
+            usage,
+            "-".to_string(),
         ]);
     }
 
-    display_table(table);
+    display_table(ctx.stdout, &table)?;
 
-    Ok(())
+    if failed {
+        Err(Error::partial())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{capacity, display_table, human, Units};
+
+    #[test]
+    fn capacity_rounding() {
+        assert_eq!(capacity(0, 100), Some(0));
+        assert_eq!(capacity(50, 50), Some(50));
+        assert_eq!(capacity(1, 199), Some(1));
+        assert_eq!(capacity(1, 2), Some(34));
+        assert_eq!(capacity(999, 1), Some(100));
+        assert_eq!(capacity(100, 0), Some(100));
+        assert_eq!(capacity(0, 0), None);
+        assert_eq!(capacity(u64::MAX, u64::MAX), Some(50));
+        // Reserved blocks count as neither used nor available
+        assert_eq!(capacity(19, 81), Some(19));
+    }
+
+    #[test]
+    fn human_sizes() {
+        assert_eq!(human(0, 1024), "0");
+        assert_eq!(human(1023, 1024), "1023");
+        assert_eq!(human(1024, 1024), "1.0K");
+        assert_eq!(human(1025, 1024), "1.1K");
+        assert_eq!(human(10 * 1024 - 1, 1024), "10K");
+        assert_eq!(human(10 * 1024 + 1, 1024), "11K");
+        assert_eq!(human(1023 * 1024 + 1, 1024), "1.0M");
+        assert_eq!(human(1000, 1000), "1.0k");
+        assert_eq!(human(1_500_000_000, 1000), "1.5G");
+        assert_eq!(human(u64::MAX, 1024), "16E");
+    }
+
+    #[test]
+    fn inode_counts() {
+        // Block sizes don't apply to inodes, but human-readable scaling does
+        assert_eq!(Units::Blocks(1024).format_count(463_308), "463308");
+        assert_eq!(Units::Human(1024).format_count(463_308), "453K");
+        assert_eq!(Units::Human(1000).format_count(463_308), "464k");
+    }
+
+    #[test]
+    fn alignment() {
+        let row = |cells: [&str; 6]| cells.map(String::from);
+        let mut out = Vec::new();
+        display_table(
+            &mut out,
+            &[
+                row([
+                    "Filesystem",
+                    "Size",
+                    "Used",
+                    "Available",
+                    "Capacity",
+                    "Mounted on",
+                ]),
+                row(["/dev/sda1", "1.0T", "10G", "1014G", "1%", "/"]),
+                row(["tmpfs", "512M", "0", "512M", "0%", "/tmp"]),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Filesystem Size Used Available Capacity Mounted on\n\
+             /dev/sda1  1.0T  10G     1014G       1% /\n\
+             tmpfs      512M    0      512M       0% /tmp\n"
+        );
+    }
 }
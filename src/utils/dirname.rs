@@ -5,15 +5,22 @@
 
 use std::path::Path;
 
+use crate::argspec::{ArgParser, ArgSpec};
+use crate::context::Context;
 use crate::err::{Error, Result};
 
-fn usage(arg0: &str) -> Error {
-    eprintln!("Usage: {arg0} path");
-    Error::new_nomsg(1)
-}
+pub const SPEC: ArgSpec = ArgSpec {
+    about: "Strip the last component from a pathname.",
+    operands: &["string"],
+    options: &[],
+    exclusive: &[],
+    raw_args: false,
+};
 
 fn dirname(path: &str) -> Result<String, Error> {
     Ok(match Path::new(&path).parent() {
+        // Relative path with only one component
+        Some(base) if base.as_os_str().is_empty() => ".".to_string(),
         Some(base) => base
             .to_str()
             .ok_or_else(|| Error::new(1, "Could not convert path".to_string()))?
@@ -22,10 +29,16 @@ fn dirname(path: &str) -> Result<String, Error> {
     })
 }
 
-pub fn util(args: &[String]) -> Result {
-    let path = args.get(1).ok_or_else(|| usage(&args[0]))?;
+pub fn util(ctx: &mut Context, args: &[String]) -> Result {
+    let mut opts = ArgParser::new(&SPEC, args);
+    opts.no_opts()?;
+
+    let path = opts
+        .positionals()
+        .next()
+        .ok_or_else(|| opts.usage_error("missing operand"))?;
 
-    println!("{}", dirname(path)?);
+    writeln!(ctx.stdout, "{}", dirname(path)?)?;
 
     Ok(())
 }
@@ -3,17 +3,20 @@
  * SPDX-License-Identifier: GPL-2.0-only
  */
 
+use crate::argspec::ArgSpec;
+use crate::context::Context;
 use crate::err::Result;
 
-#[allow(clippy::unnecessary_wraps)]
-pub fn util(args: &[String]) -> Result {
-    if args.len() > 1 {
-        for arg in &args[1..] {
-            println!("{arg}");
-        }
-    } else {
-        println!();
-    }
+pub const SPEC: ArgSpec = ArgSpec {
+    about: "Write arguments to standard output.",
+    operands: &["[string...]"],
+    options: &[],
+    exclusive: &[],
+    raw_args: true,
+};
+
+pub fn util(ctx: &mut Context, args: &[String]) -> Result {
+    writeln!(ctx.stdout, "{}", args[1..].join(" "))?;
 
     Ok(())
 }
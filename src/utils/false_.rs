@@ -3,8 +3,18 @@
  * SPDX-License-Identifier: GPL-2.0-only
  */
 
+use crate::argspec::ArgSpec;
+use crate::context::Context;
 use crate::err::{Error, Result};
 
-pub const fn util(_args: &[String]) -> Result {
+pub const SPEC: ArgSpec = ArgSpec {
+    about: "Return false value (exit status 1). Any arguments are ignored.",
+    operands: &[],
+    options: &[],
+    exclusive: &[],
+    raw_args: true,
+};
+
+pub fn util(_ctx: &mut Context, _args: &[String]) -> Result {
     Err(Error::new_nomsg(1))
 }
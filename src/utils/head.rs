@@ -3,89 +3,257 @@
  * SPDX-License-Identifier: GPL-2.0-only
  */
 
-use std::fs::File;
-use std::io::{self, BufRead};
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
 use std::str::FromStr;
 
-use getargs::{Opt, Options};
+use getargs::Opt;
 
-use crate::bufinput::BufInput;
+use crate::argspec::{ArgParser, ArgSpec, OptSpec};
+use crate::bufinput::{BufInput, Operands};
+use crate::context::Context;
 use crate::err::{Error, Result};
 
-fn usage(arg0: &str) {
-    eprintln!("Usage: {arg0} [-n] lines [-h|--help] [FILE] ...");
+pub const SPEC: ArgSpec = ArgSpec {
+    about: "Print the first lines of each file.",
+    operands: &["[file...]"],
+    options: &[
+        OptSpec::short(
+            'c',
+            "print the first number bytes (-number: all but the last number)",
+        )
+        .alias("bytes")
+        .value("number"),
+        OptSpec::short(
+            'n',
+            "print the first number lines (default 10; -number: all but the last number)",
+        )
+        .alias("lines")
+        .value("number"),
+        OptSpec::short('q', "never print headers giving file names").alias("quiet"),
+        OptSpec::short('v', "always print headers giving file names").alias("verbose"),
+        OptSpec::short('z', "lines end with NUL, not newline").alias("zero-terminated"),
+    ],
+    exclusive: &[&['c', 'n']],
+    raw_args: false,
+};
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Unit {
+    Bytes,
+    Lines,
 }
 
-pub fn util(args: &[String]) -> Result {
-    let mut total = 10u64; // POSIX default
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Count {
+    // This many units from the start
+    First(u64),
+    // Everything except this many units at the end
+    AllBut(u64),
+}
 
-    let mut opts = Options::new(args.iter().skip(1).map(String::as_str));
-    while let Some(opt) = opts.next_opt().expect("argument parsing error") {
-        match opt {
-            Opt::Short('n') => {
-                let Ok(arg) = opts.value() else { return Err(Error::new(1, "-n must have a total".to_string())) };
-
-                match u64::from_str(arg) {
-                    Ok(result) => total = result,
-                    Err(e) => {
-                        return Err(Error::new(1, format!("Invalid total: {e}")));
-                    }
-                }
+#[derive(Copy, Clone, PartialEq, Eq)]
+struct Amount {
+    unit: Unit,
+    count: Count,
+    // What ends a line: a newline, or a NUL with -z
+    delimiter: u8,
+}
+
+// "N" is from the start, "-N" is all but the last N
+fn parse_count(value: &str) -> Option<Count> {
+    value
+        .strip_prefix('-')
+        .map_or_else(
+            || u64::from_str(value).map(Count::First),
+            |number| u64::from_str(number).map(Count::AllBut),
+        )
+        .ok()
+}
+
+/* head -20 and friends are obsolescent, but scripts still use them. Only the first argument is
+ * looked at, same as tail; c, b (512-byte blocks), and l suffixes are understood.
+ * --Elizafox
+ */
+fn obsolete_args(args: &[String]) -> Option<Vec<String>> {
+    let rest = args.get(1)?.strip_prefix('-')?;
+    let digits = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    if digits == 0 {
+        return None;
+    }
+
+    let number = u64::from_str(&rest[..digits]).ok()?;
+    let (unit, number) = match &rest[digits..] {
+        "" | "l" => ("-n", number),
+        "c" => ("-c", number),
+        "b" => ("-c", number.checked_mul(512)?),
+        _ => return None,
+    };
+
+    let mut new_args = vec![args[0].clone(), unit.to_string(), number.to_string()];
+    new_args.extend_from_slice(&args[2..]);
+    Some(new_args)
+}
+
+/* Lines are copied exactly as they are, a buffer at a time, without looking at what's in them
+ * beyond where they end. Bad UTF-8, carriage returns, and a missing newline at the end all come out
+ * just as they went in.
+ * --Elizafox
+ */
+fn first_units(
+    input: &mut dyn BufRead,
+    out: &mut dyn Write,
+    amount: Amount,
+    mut count: u64,
+) -> io::Result<()> {
+    while count > 0 {
+        let buffer = input.fill_buf()?;
+        if buffer.is_empty() {
+            break;
+        }
+
+        let used = match amount.unit {
+            Unit::Bytes => {
+                let used = usize::try_from(count).map_or(buffer.len(), |c| c.min(buffer.len()));
+                count -= used as u64;
+                used
+            }
+            Unit::Lines => {
+                buffer
+                    .iter()
+                    .position(|b| *b == amount.delimiter)
+                    .map_or(buffer.len(), |i| {
+                        count -= 1;
+                        i + 1
+                    })
             }
-            Opt::Short('h') | Opt::Long("help") => {
-                usage(&args[0]);
-                return Ok(());
+        };
+        out.write_all(&buffer[..used])?;
+        input.consume(used);
+    }
+
+    Ok(())
+}
+
+/* We can't know what the last few units are until the input ends, so hold back that many in a ring
+ * and write out whatever falls off the front of it. Memory is bounded by the count (or the input,
+ * whichever is smaller), never the whole file.
+ * --Elizafox
+ */
+fn all_but_bytes(input: &mut dyn BufRead, out: &mut dyn Write, count: u64) -> io::Result<()> {
+    let count = usize::try_from(count).unwrap_or(usize::MAX);
+    let mut ring = VecDeque::new();
+    loop {
+        let buffer = input.fill_buf()?;
+        if buffer.is_empty() {
+            return Ok(());
+        }
+
+        let used = buffer.len();
+        ring.extend(buffer);
+        input.consume(used);
+
+        if ring.len() > count {
+            let excess = ring.len() - count;
+            let (front, back) = ring.as_slices();
+            if excess <= front.len() {
+                out.write_all(&front[..excess])?;
+            } else {
+                out.write_all(front)?;
+                out.write_all(&back[..excess - front.len()])?;
             }
-            _ => {}
+            ring.drain(..excess);
         }
     }
+}
 
-    let mut files: Vec<(&str, BufInput)> = Vec::new();
-
-    for filename in opts.positionals() {
-        if filename == "-" {
-            files.push(("stdin", BufInput::Standard(io::stdin().lock())));
-        } else {
-            let file = File::open(filename);
-            match file {
-                Ok(file) => {
-                    files.push((filename, BufInput::File(io::BufReader::new(file))));
-                }
-                Err(e) => {
-                    return Err(Error::new(
-                        1,
-                        format!("Could not open file: {filename}: {e}"),
-                    ));
-                }
+fn all_but_lines(
+    input: &mut dyn BufRead,
+    out: &mut dyn Write,
+    count: u64,
+    delimiter: u8,
+) -> io::Result<()> {
+    let count = usize::try_from(count).unwrap_or(usize::MAX);
+    let mut ring: VecDeque<Vec<u8>> = VecDeque::new();
+    loop {
+        let mut line = Vec::new();
+        if input.read_until(delimiter, &mut line)? == 0 {
+            return Ok(());
+        }
+
+        ring.push_back(line);
+        if ring.len() > count {
+            if let Some(line) = ring.pop_front() {
+                out.write_all(&line)?;
             }
         }
     }
+}
 
-    if files.is_empty() {
-        // If ain't nobody got me, stdin got me.
-        files.push(("stdin", BufInput::Standard(io::stdin().lock())));
+fn head(mut input: BufInput, out: &mut dyn Write, amount: Amount) -> io::Result<()> {
+    match (amount.count, amount.unit) {
+        (Count::First(count), _) => first_units(&mut input, out, amount, count),
+        (Count::AllBut(count), Unit::Bytes) => all_but_bytes(&mut input, out, count),
+        (Count::AllBut(count), Unit::Lines) => {
+            all_but_lines(&mut input, out, count, amount.delimiter)
+        }
     }
+}
+
+pub fn util(ctx: &mut Context, args: &[String]) -> Result {
+    let args = obsolete_args(args).unwrap_or_else(|| args.to_vec());
 
-    for (filename, file) in files {
-        let mut count = 0u64;
+    // POSIX default
+    let mut amount = Amount {
+        unit: Unit::Lines,
+        count: Count::First(10),
+        delimiter: b'\n',
+    };
+    let mut headers = None;
 
-        let lines = file.lines();
-        for line in lines {
-            count += 1;
-            if count > total {
-                break;
+    let mut opts = ArgParser::new(&SPEC, &args);
+    while let Some(opt) = opts.next_opt()? {
+        match opt {
+            Opt::Short(c @ ('c' | 'n')) => {
+                let (unit, what) = if c == 'c' {
+                    (Unit::Bytes, "bytes")
+                } else {
+                    (Unit::Lines, "lines")
+                };
+                let count = parse_count(opts.value()).ok_or_else(|| {
+                    opts.usage_error(&format!("invalid number of {what}: '{}'", opts.value()))
+                })?;
+                amount.unit = unit;
+                amount.count = count;
             }
+            Opt::Short('q') => headers = Some(false),
+            Opt::Short('v') => headers = Some(true),
+            Opt::Short('z') => amount.delimiter = b'\0',
+            _ => unreachable!(),
+        }
+    }
+
+    let names: Vec<&str> = opts.positionals().collect();
+    let headers = headers.unwrap_or(names.len() > 1);
 
-            match line {
-                Ok(line) => {
-                    println!("{line}");
-                }
-                Err(e) => {
-                    return Err(Error::new(1, format!("Error reading from {filename}: {e}")));
-                }
+    let mut first = true;
+    let mut operands = Operands::new(names);
+    while let Some(operand) = operands.open_next(ctx) {
+        let label = operand.label();
+        if headers {
+            if !first {
+                writeln!(ctx.stdout)?;
             }
+            writeln!(ctx.stdout, "==> {label} <==")?;
+        }
+        first = false;
+
+        if let Err(e) = head(operand.input(ctx.stdin), ctx.stdout, amount) {
+            operands.report(ctx, &Error::io(label, e));
         }
     }
 
-    Ok(())
+    operands.finish()
 }
@@ -5,11 +5,40 @@
 
 use std::path::Path;
 
-use getargs::{Opt, Options};
+#[cfg(feature = "ln")]
+use getargs::Opt;
 
+#[cfg(feature = "ln")]
+use crate::argspec::OptSpec;
+use crate::argspec::{ArgParser, ArgSpec};
+use crate::context::Context;
 use crate::err::{Error, Result};
 
+#[cfg(feature = "ln")]
+pub const SPEC_LN: ArgSpec = ArgSpec {
+    about: "Make links between files.",
+    operands: &["source_file target_file", "source_file... target_dir"],
+    options: &[
+        OptSpec::short('f', "remove existing destination files").alias("force"),
+        OptSpec::short('s', "make symbolic links instead of hard links").alias("symbolic"),
+        OptSpec::short('L', "hard link to the target of a symbolic link source").alias("logical"),
+        OptSpec::short('P', "hard link to a symbolic link source itself").alias("physical"),
+    ],
+    exclusive: &[&['L', 'P']],
+    raw_args: false,
+};
+
+#[cfg(feature = "link")]
+pub const SPEC_LINK: ArgSpec = ArgSpec {
+    about: "Make a hard link to a file.",
+    operands: &["source_file target_file"],
+    options: &[],
+    exclusive: &[],
+    raw_args: false,
+};
+
 #[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(not(feature = "ln"), allow(dead_code))] // link only ever makes plain hard links
 enum LinkType {
     HardlinkNormal,
     Symlink,
@@ -30,13 +59,14 @@ fn perform_link<A: AsRef<Path>, B: AsRef<Path>>(
     let old_c_str = CString::new(old.as_ref().as_os_str().as_bytes()).unwrap();
     let new_c_str = CString::new(new.as_ref().as_os_str().as_bytes()).unwrap();
 
+    // Must be called straight after the failing call, so errno is still good
+    let new_name = new.as_ref().to_string_lossy();
+    let fail = |what: &str| Err(Error::io(&new_name, io::Error::last_os_error()).context(what));
+
     if force && new.as_ref().exists() {
         let ret = unsafe { unlink(new_c_str.as_ptr()) };
         if ret != 0 {
-            return Err(Error::new(
-                1,
-                format!("Could not unlink: {}", io::Error::last_os_error()),
-            ));
+            return fail("Could not remove");
         }
     }
 
@@ -44,19 +74,13 @@ fn perform_link<A: AsRef<Path>, B: AsRef<Path>>(
         LinkType::Symlink => {
             let ret = unsafe { symlink(old_c_str.as_ptr(), new_c_str.as_ptr()) };
             if ret != 0 {
-                return Err(Error::new(
-                    1,
-                    format!("Could not do symlink: {}", io::Error::last_os_error()),
-                ));
+                return fail("Could not create symlink");
             }
         }
         LinkType::HardlinkNormal => {
             let ret = unsafe { link(old_c_str.as_ptr(), new_c_str.as_ptr()) };
             if ret != 0 {
-                return Err(Error::new(
-                    1,
-                    format!("Could not do hard link: {}", io::Error::last_os_error()),
-                ));
+                return fail("Could not create hard link");
             }
         }
         LinkType::HardlinkToSymlink => {
@@ -70,10 +94,7 @@ fn perform_link<A: AsRef<Path>, B: AsRef<Path>>(
                 )
             };
             if ret != 0 {
-                return Err(Error::new(
-                    1,
-                    format!("Could not do hard link: {}", io::Error::last_os_error()),
-                ));
+                return fail("Could not create hard link");
             }
         }
         LinkType::HardlinkToSymlinkDirect => {
@@ -87,10 +108,7 @@ fn perform_link<A: AsRef<Path>, B: AsRef<Path>>(
                 )
             };
             if ret != 0 {
-                return Err(Error::new(
-                    1,
-                    format!("Could not do hard link: {}", io::Error::last_os_error()),
-                ));
+                return fail("Could not create hard link");
             }
         }
     }
@@ -109,73 +127,59 @@ fn perform_link<A: AsRef<Path>, B: AsRef<Path>>(
     use symlink::{remove_symlink_auto, symlink_auto};
 
     if force && new.as_ref().exists() {
-        remove_symlink_auto(new)
-            .map_err(|e| Error::new(1, format!("Could not remove file or directory: {e}")))?;
+        remove_symlink_auto(new).map_err(|e| {
+            Error::io(&new.as_ref().to_string_lossy(), e).context("Could not remove")
+        })?;
     }
 
     if link_type == LinkType::Symlink {
-        symlink_auto(old, new)
-            .map_err(|e| Error::new(1, format!("Could not create symlink: {e}")))?;
+        symlink_auto(old, new).map_err(|e| {
+            Error::io(&new.as_ref().to_string_lossy(), e).context("Could not create symlink")
+        })?;
     } else {
-        if link_type == LinkType::HardlinkToSymlink
-            || link_type == LinkType::HardlinkToSymlinkDirect
-        {
-            // FIXME
-            eprintln!("WARNING: -F and -L are presently ignored on Windows");
-        }
-
-        hard_link(old, new)
-            .map_err(|e| Error::new(1, format!("Could not create hard link: {e}")))?;
+        hard_link(old, new).map_err(|e| {
+            Error::io(&new.as_ref().to_string_lossy(), e).context("Could not create hard link")
+        })?;
     }
 
     Ok(())
 }
 
-fn usage_ln(arg0: &str) -> Error {
-    eprintln!("Usage: {arg0} [-fs] [-L|-P] source_file target_file");
-    eprintln!("Usage: {arg0} [-fs] [-L|-P] source_file... target_dir");
-    Error::new_nomsg(1)
-}
-
-fn usage_link(arg0: &str) -> Error {
-    eprintln!("Usage: {arg0} source_file target_file");
-    Error::new_nomsg(1)
-}
-
-pub fn util_ln(args: &[String]) -> Result {
+#[cfg(feature = "ln")]
+pub fn util_ln(ctx: &mut Context, args: &[String]) -> Result {
     let mut link_type: LinkType = LinkType::HardlinkNormal;
     let mut force = false;
 
-    let mut opts = Options::new(args.iter().skip(1).map(String::as_str));
-    while let Some(opt) = opts.next_opt().expect("argument parsing error") {
+    let mut opts = ArgParser::new(&SPEC_LN, args);
+    while let Some(opt) = opts.next_opt()? {
         match opt {
-            Opt::Short('h') | Opt::Long("help") => {
-                usage_ln(&args[0]);
-                return Ok(());
-            }
             Opt::Short('s') => link_type = LinkType::Symlink,
             Opt::Short('f') => force = true,
-            Opt::Short('L') => {
-                if link_type == LinkType::HardlinkToSymlinkDirect {
-                    eprintln!("Error: -L conflicts with -P");
-                    return Err(usage_ln(&args[0]));
-                }
-                link_type = LinkType::HardlinkToSymlink;
-            }
-            Opt::Short('P') => {
-                if link_type == LinkType::HardlinkToSymlink {
-                    eprintln!("Error: -P conflicts with -L");
-                    return Err(usage_ln(&args[0]));
-                }
-                link_type = LinkType::HardlinkToSymlinkDirect;
-            }
-            _ => {}
+            Opt::Short('L') => link_type = LinkType::HardlinkToSymlink,
+            Opt::Short('P') => link_type = LinkType::HardlinkToSymlinkDirect,
+            _ => unreachable!(),
+        }
+    }
+
+    // FIXME
+    #[cfg(windows)]
+    {
+        if matches!(
+            link_type,
+            LinkType::HardlinkToSymlink | LinkType::HardlinkToSymlinkDirect
+        ) {
+            ctx.report(&Error::new(
+                1,
+                "-L and -P are presently ignored on Windows".to_string(),
+            ));
         }
     }
 
+    let mut failed = false;
     let mut positionals = opts.positionals().collect::<Vec<_>>();
     match positionals.len() {
-        0 | 1 => return Err(usage_ln(&args[0])),
+        0 => return Err(opts.usage_error("missing file operand")),
+        1 => return Err(opts.usage_error("missing destination file operand")),
         2 => {
             let old = Path::new(positionals.first().expect("Could not get argument"));
             let new = Path::new(positionals.get(1).expect("Could not get argument"));
@@ -186,7 +190,7 @@ pub fn util_ln(args: &[String]) -> Result {
             if !dir.exists() {
                 return Err(Error::new(
                     1,
-                    "Error creating links: no such directory".to_string(),
+                    format!("{}: No such directory", dir.to_string_lossy()),
                 ));
             }
 
@@ -194,27 +198,36 @@ pub fn util_ln(args: &[String]) -> Result {
                 let old = Path::new(file);
                 let new = dir.join(file);
                 if let Err(e) = perform_link(&old, &new, link_type, force) {
-                    eprintln!(
-                        "Error creating {}: {}",
-                        new.to_string_lossy(),
-                        e.message.unwrap_or_else(|| "unknown".to_string())
-                    );
+                    ctx.report(&e);
+                    failed = true;
                 }
             }
         }
     }
 
-    Ok(())
+    if failed {
+        Err(Error::partial())
+    } else {
+        Ok(())
+    }
 }
 
 // The implementation of link is significantly simpler
-pub fn util_link(args: &[String]) -> Result {
-    if args.len() < 3 {
-        return Err(usage_link(&args[0]));
-    }
-
-    let old = Path::new(&args[1]);
-    let new = Path::new(&args[2]);
-    perform_link(&old, &new, LinkType::HardlinkNormal, false)?;
+#[cfg(feature = "link")]
+pub fn util_link(_ctx: &mut Context, args: &[String]) -> Result {
+    let mut opts = ArgParser::new(&SPEC_LINK, args);
+    opts.no_opts()?;
+
+    let positionals = opts.positionals().collect::<Vec<_>>();
+    let [old, new] = positionals[..] else {
+        return Err(opts.usage_error("exactly two file operands required"));
+    };
+
+    perform_link(
+        &Path::new(old),
+        &Path::new(new),
+        LinkType::HardlinkNormal,
+        false,
+    )?;
     Ok(())
 }
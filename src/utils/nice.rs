@@ -3,67 +3,86 @@
  * SPDX-License-Identifier: GPL-2.0-only
  */
 
-use getargs::{Opt, Options};
+#[cfg(unix)]
+use std::io;
+use std::process::Command;
+
+use getargs::Opt;
 
+use crate::argspec::{ArgParser, ArgSpec, OptSpec};
+use crate::context::Context;
 use crate::err::{Error, Result};
 
+pub const SPEC: ArgSpec = ArgSpec {
+    about: "Run a command with a modified scheduling priority.",
+    operands: &["command [argument...]"],
+    options: &[
+        OptSpec::short('n', "add increment to the niceness (default 10)")
+            .alias("adjustment")
+            .value("increment"),
+    ],
+    exclusive: &[],
+    raw_args: false,
+};
+
 #[cfg(unix)]
-fn set_priority(niceness: i32) {
+fn get_priority() -> io::Result<i32> {
     use errno::{errno, set_errno, Errno};
-    use libc::{getpriority, setpriority, PRIO_PROCESS};
-    use std::io;
+    use libc::{getpriority, PRIO_PROCESS};
 
+    // -1 is a perfectly good priority, so only errno can tell us it failed
     set_errno(Errno(0));
-    let mut current_priority = unsafe { getpriority(PRIO_PROCESS, 0) };
-    if current_priority == -1 && errno().0 != 0 {
-        eprintln!(
-            "Could not get process priority: {}",
-            io::Error::last_os_error()
-        );
-        current_priority = 0;
+    let priority = unsafe { getpriority(PRIO_PROCESS, 0) };
+    if priority == -1 && errno().0 != 0 {
+        return Err(io::Error::last_os_error());
     }
 
-    let new_priority = niceness + current_priority;
-    if unsafe { setpriority(PRIO_PROCESS, 0, new_priority) } < 0 {
-        eprintln!(
-            "Could not set process priority: {}",
-            io::Error::last_os_error()
-        );
+    Ok(priority)
+}
+
+#[cfg(unix)]
+fn set_priority(priority: i32) -> io::Result<()> {
+    use libc::{setpriority, PRIO_PROCESS};
+
+    if unsafe { setpriority(PRIO_PROCESS, 0, priority) } < 0 {
+        return Err(io::Error::last_os_error());
     }
+
+    Ok(())
 }
 
+/* As the real nice, we set our own priority and the command inherits it, just as if we'd exec'd
+ * it; if we can't, we say so and run it anyway, as POSIX and everyone else do. Embedded, we mustn't
+ * touch the priority of whoever's hosting us, so it's done in the child, and a failure there is
+ * returned from running the command, since there's nobody left to tell.
+ * --Elizafox
+ */
 #[cfg(unix)]
-fn spawn_process(niceness: i32, command: &str, args: &[&str]) -> Result {
+fn niced_command(ctx: &mut Context, niceness: i32, command: &str, args: &[&str]) -> Command {
     use std::os::unix::process::CommandExt;
-    use std::process::Command;
-
-    let mut cmd = unsafe {
-        Command::new(command)
-            .args(args)
-            .pre_exec(move || {
-                set_priority(niceness);
-                Ok(())
-            })
-            .spawn()
-            .map_err(|e| Error::new(1, format!("Could not spawn command: {e}")))?
-    };
 
-    let status = cmd
-        .wait()
-        .map_err(|e| Error::new(1, format!("Command not running: {e}")))?;
-    status.code().map_or(Ok(()), |code| {
-        if code == 0 {
-            Ok(())
-        } else {
-            Err(Error::new_nomsg(code))
+    let priority = get_priority().unwrap_or_else(|e| {
+        ctx.report(&Error::from(e).context("Could not get process priority"));
+        0
+    }) + niceness;
+
+    let mut cmd = Command::new(command);
+    cmd.args(args);
+    if ctx.is_process() {
+        if let Err(e) = set_priority(priority) {
+            ctx.report(&Error::from(e).context("Could not set process priority"));
         }
-    })
+    } else {
+        unsafe {
+            cmd.pre_exec(move || set_priority(priority));
+        }
+    }
+    cmd
 }
 
 #[cfg(windows)]
-fn spawn_process(niceness: i32, command: &str, args: &[&str]) -> Result {
+fn niced_command(_ctx: &mut Context, niceness: i32, command: &str, args: &[&str]) -> Command {
     use std::os::windows::process::CommandExt;
-    use std::process::Command;
     use windows::Win32::System::Threading::{
         ABOVE_NORMAL_PRIORITY_CLASS, BELOW_NORMAL_PRIORITY_CLASS, HIGH_PRIORITY_CLASS,
         IDLE_PRIORITY_CLASS, NORMAL_PRIORITY_CLASS, REALTIME_PRIORITY_CLASS,
@@ -97,59 +116,42 @@ fn spawn_process(niceness: i32, command: &str, args: &[&str]) -> Result {
         }
     };
 
-    let mut cmd = Command::new(command)
-        .args(args)
-        .creation_flags(priority)
-        .spawn()
-        .map_err(|e| Error::new(1, format!("Could not spawn command: {e}")))?;
-
-    let status = cmd
-        .wait()
-        .map_err(|e| Error::new(1, format!("Command not running: {e}")))?;
-    status.code().map_or(Ok(()), |code| {
-        if code == 0 {
-            Ok(())
-        } else {
-            Err(Error::new_nomsg(code))
-        }
-    })
-}
-
-fn usage(arg0: &str) -> Error {
-    Error::new(1, format!("Usage: {arg0} [-n number] command..."))
+    let mut cmd = Command::new(command);
+    cmd.args(args).creation_flags(priority);
+    cmd
 }
 
-pub fn util(args: &[String]) -> Result {
+pub fn util(ctx: &mut Context, args: &[String]) -> Result {
     let mut niceness = 10i32;
 
-    let mut opts = Options::new(args.iter().skip(1).map(String::as_str));
-    while let Some(opt) = opts.next_opt().expect("argument parsing error") {
+    let mut opts = ArgParser::new(&SPEC, args);
+    while let Some(opt) = opts.next_opt()? {
         match opt {
-            Opt::Short('h') | Opt::Long("help") => {
-                eprintln!("{}", usage(&args[0]));
-                return Ok(());
-            }
             Opt::Short('n') => {
-                let niceness_str = opts.value().map_err(|_| {
-                    eprintln!("Error: No niceness value specified");
-                    usage(&args[0])
-                })?;
-
-                niceness = niceness_str.parse::<i32>().map_err(|_| {
-                    eprintln!("Bad niceness value");
-                    usage(&args[0])
-                })?;
+                niceness = opts
+                    .value()
+                    .parse::<i32>()
+                    .map_err(|_| opts.usage_error("bad niceness value"))?;
             }
-            _ => {}
+            _ => unreachable!(),
         }
     }
 
     let args = opts.positionals().collect::<Vec<_>>();
 
     if args.is_empty() {
-        eprintln!("No command specified");
-        return Err(usage(args[0]));
+        return Err(opts.usage_error("no command specified"));
     }
 
-    spawn_process(niceness, args[0], &args[1..])
+    let mut cmd = niced_command(ctx, niceness, args[0], &args[1..]);
+    let status = ctx
+        .run_command(&mut cmd)
+        .map_err(|e| Error::exec(args[0], e))?;
+    status.code().map_or(Ok(()), |code| {
+        if code == 0 {
+            Ok(())
+        } else {
+            Err(Error::new_nomsg(code))
+        }
+    })
 }
@@ -6,23 +6,41 @@
 use std::env;
 use std::io;
 
-use getargs::{Opt, Options};
+use getargs::Opt;
 
+use crate::argspec::{ArgParser, ArgSpec, OptSpec};
+use crate::context::Context;
 use crate::err::Result;
 
+pub const SPEC: ArgSpec = ArgSpec {
+    about: "Print the name of the current working directory.",
+    operands: &[],
+    options: &[
+        OptSpec::short(
+            'L',
+            "use PWD from the environment, even if it contains symlinks",
+        )
+        .alias("logical"),
+        OptSpec::short('P', "resolve all symlinks").alias("physical"),
+    ],
+    exclusive: &[],
+    raw_args: false,
+};
+
 #[cfg(unix)]
-fn getcwd_logical() -> io::Result<String> {
+fn getcwd_logical(ctx: &Context) -> io::Result<String> {
     use std::fs::metadata;
     use std::os::unix::fs::MetadataExt;
 
-    let pwd = env::var("PWD")
-        .map_err(|_| io::Error::new(io::ErrorKind::Other, "Could not get PWD env var"))?;
+    let pwd = ctx
+        .var("PWD")
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "Could not get PWD env var"))?;
     if pwd.starts_with('/') {
-        let logical_md = metadata(&pwd)?;
+        let logical_md = metadata(pwd)?;
         let physical_md = metadata(".")?;
 
         if logical_md.dev() == physical_md.dev() && logical_md.ino() == physical_md.ino() {
-            return Ok(pwd);
+            return Ok(pwd.to_string());
         }
     }
 
@@ -30,42 +48,38 @@ fn getcwd_logical() -> io::Result<String> {
 }
 
 #[cfg(windows)]
-fn getcwd_logical() -> io::Result<String> {
+fn getcwd_logical(ctx: &Context) -> io::Result<String> {
     // Might be set by MingW
-    let pwd = env::var("PWD")
-        .map_err(|_| io::Error::new(io::ErrorKind::Other, "Could not get PWD env var"))?;
-    Ok(pwd)
+    let pwd = ctx
+        .var("PWD")
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "Could not get PWD env var"))?;
+    Ok(pwd.to_string())
 }
 
-#[allow(clippy::unnecessary_wraps)]
-pub fn util(args: &[String]) -> Result {
+pub fn util(ctx: &mut Context, args: &[String]) -> Result {
     let mut logical: bool = true;
 
-    let mut opts = Options::new(args.iter().skip(1).map(String::as_str));
-    while let Some(opt) = opts.next_opt().expect("argument parsing error") {
+    let mut opts = ArgParser::new(&SPEC, args);
+    while let Some(opt) = opts.next_opt()? {
         match opt {
             Opt::Short('L') => logical = true,
             Opt::Short('P') => logical = false,
-            Opt::Short('h') | Opt::Long("help") => {
-                eprintln!("Usage: {} [-L|-P]", args[0]);
-                return Ok(());
-            }
-            _ => {}
+            _ => unreachable!(),
         }
     }
 
     if logical {
         // If this fails we will try physical
-        if let Ok(dir) = getcwd_logical() {
-            println!("{dir}");
+        if let Ok(dir) = getcwd_logical(ctx) {
+            writeln!(ctx.stdout, "{dir}")?;
             return Ok(());
         }
     }
 
-    env::current_dir().map_or_else(
-        |_| println!("."),
-        |dir| println!("{}", dir.to_str().unwrap_or(".")),
-    );
+    match env::current_dir() {
+        Ok(dir) => writeln!(ctx.stdout, "{}", dir.to_str().unwrap_or("."))?,
+        Err(_) => writeln!(ctx.stdout, ".")?,
+    }
 
     Ok(())
 }
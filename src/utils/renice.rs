@@ -4,29 +4,41 @@
  */
 
 #[allow(unused_imports)]
-use getargs::{Opt, Options};
+use getargs::Opt;
 
+#[allow(unused_imports)]
+use crate::argspec::{ArgParser, ArgSpec, OptSpec};
+use crate::context::Context;
 use crate::err::{Error, Result};
 
 #[cfg(unix)]
-fn usage(arg0: &str) -> Error {
-    Error::new(1, format!("Usage: {arg0} [-g|-p|-u] [-n number] ID..."))
-}
+pub const SPEC: ArgSpec = ArgSpec {
+    about: "Alter the scheduling priority of running processes.",
+    operands: &["ID..."],
+    options: &[
+        OptSpec::short('g', "treat IDs as process group IDs"),
+        OptSpec::short('p', "treat IDs as process IDs (the default)"),
+        OptSpec::short('u', "treat IDs as user names or user IDs"),
+        OptSpec::short('n', "add increment to the niceness (default 10)").value("increment"),
+    ],
+    exclusive: &[&['g', 'p', 'u']],
+    raw_args: false,
+};
 
 /* I don't intend to implement -g or -u for Windows, so it uses a different implementation
  * entirely. --Elizafox
  */
 #[cfg(windows)]
-pub const fn util(_args: &[String]) -> Result {
-    Err(Error::new(255, "Not implemented on Windows yet"))
+pub fn util(_ctx: &mut Context, _args: &[String]) -> Result {
+    Err(Error::new(1, "Not implemented on Windows yet".to_string()))
 }
 
-/* This tries to mirror POSIX behaviour, although the return code mechanism works like BSD. POSIX
- * doesn't specify the return code AFAICT, so this is okay, and it's somewhat compatible.
+/* This tries to mirror POSIX behaviour. IDs we can't do anything with are reported as we go, and
+ * we carry on with the rest; the exit status is 1 if any of them failed.
  * --Elizafox
  */
 #[cfg(unix)]
-pub fn util(args: &[String]) -> Result {
+pub fn util(ctx: &mut Context, args: &[String]) -> Result {
     use std::ffi::{c_int, CString};
     use std::io;
 
@@ -35,37 +47,27 @@ pub fn util(args: &[String]) -> Result {
 
     let mut niceness: c_int = 10;
     let mut which = PRIO_PROCESS;
-    let mut errs = 0i32;
+    let mut failed = false;
 
-    let mut opts = Options::new(args.iter().skip(1).map(String::as_str));
-    while let Some(opt) = opts.next_opt().expect("argument parsing error") {
+    let mut opts = ArgParser::new(&SPEC, args);
+    while let Some(opt) = opts.next_opt()? {
         match opt {
-            Opt::Short('h') | Opt::Long("help") => {
-                eprintln!("{}", usage(&args[0]));
-                return Ok(());
-            }
             Opt::Short('g') => which = PRIO_PGRP,
             Opt::Short('u') => which = PRIO_USER,
             Opt::Short('p') => which = PRIO_PROCESS,
             Opt::Short('n') => {
-                let niceness_str = opts.value().map_err(|_| {
-                    eprintln!("Error: No niceness value specified");
-                    usage(&args[0])
-                })?;
-
-                niceness = niceness_str.parse::<i32>().map_err(|_| {
-                    eprintln!("Bad niceness value");
-                    usage(&args[0])
-                })?;
+                niceness = opts
+                    .value()
+                    .parse::<i32>()
+                    .map_err(|_| opts.usage_error("bad niceness value"))?;
             }
-            _ => {}
+            _ => unreachable!(),
         }
     }
 
     let args = opts.positionals().collect::<Vec<_>>();
     if args.is_empty() {
-        eprintln!("No ID's specified");
-        return Err(usage(args[0]));
+        return Err(opts.usage_error("no IDs specified"));
     }
 
     for str_id in args {
@@ -83,8 +85,8 @@ pub fn util(args: &[String]) -> Result {
             if let Ok(i) = str_id.parse::<id_t>() {
                 int_id = Some(i);
             } else {
-                eprintln!("{str_id}: invalid ID");
-                errs += 1;
+                ctx.report(&Error::new(1, format!("{str_id}: invalid ID")));
+                failed = true;
                 continue;
             }
         }
@@ -95,26 +97,22 @@ pub fn util(args: &[String]) -> Result {
         set_errno(Errno(0));
         let current_priority = unsafe { getpriority(which, int_id) };
         if current_priority == -1 && errno().0 != 0 {
-            eprintln!(
-                "Could not get priority for {int_id}: {}",
-                io::Error::last_os_error()
-            );
-            errs += 1;
+            let e = io::Error::last_os_error();
+            ctx.report(&Error::io(str_id, e).context("Could not get priority"));
+            failed = true;
             continue;
         }
 
         let new_priority = niceness + current_priority;
         if unsafe { setpriority(which, int_id, new_priority) } < 0 {
-            eprintln!(
-                "Could not set priority for {int_id}: {}",
-                io::Error::last_os_error()
-            );
-            errs += 1;
+            let e = io::Error::last_os_error();
+            ctx.report(&Error::io(str_id, e).context("Could not set priority"));
+            failed = true;
         }
     }
 
-    if errs > 0 {
-        Err(Error::new_nomsg(errs))
+    if failed {
+        Err(Error::partial())
     } else {
         Ok(())
     }
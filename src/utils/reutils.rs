@@ -5,62 +5,142 @@
 
 use std::process::Command;
 
-use getargs::{Opt, Options};
+use getargs::Opt;
 
+use crate::argspec::{ArgParser, ArgSpec, OptSpec};
+use crate::completions::{write_completions, Shell};
+use crate::context::Context;
 use crate::err::{Error, Result};
-use crate::install::perform;
+use crate::install::{perform, uninstall, Options};
+use crate::man::write_man;
 use crate::utils::{paths, DISPATCH_TABLE};
 use crate::version::about;
 
-pub fn util(args: &[String]) -> Result {
+pub const SPEC: ArgSpec = ArgSpec {
+    about: "Run a reutils utility, or manage the reutils installation.",
+    operands: &["[basedir]", "utility [argument...]"],
+    options: &[
+        OptSpec::short('v', "print version and build information").alias("version"),
+        OptSpec::long("install", "install reutils and its utilities into basedir"),
+        OptSpec::long("uninstall", "remove reutils and its utilities from basedir"),
+        OptSpec::long(
+            "dry-run",
+            "only show what installing or uninstalling would do",
+        ),
+        OptSpec::long("force", "when installing, replace files that aren't ours"),
+        OptSpec::long(
+            "hardlinks",
+            "when installing, use hard links instead of symlinks",
+        ),
+        OptSpec::long("usr-merged", "when installing, put everything under usr/"),
+        OptSpec::long("list-tools", "list all utilities and their install paths"),
+        OptSpec::long("man", "print the man page for utility, in roff format").value("utility"),
+        OptSpec::long(
+            "completions",
+            "print completions for shell (bash, zsh, or fish)",
+        )
+        .value("shell"),
+    ],
+    exclusive: &[],
+    raw_args: false,
+};
+
+pub fn util(ctx: &mut Context, args: &[String]) -> Result {
     if args.len() <= 1 {
-        about(false);
+        about(ctx.stderr, false)?;
         return Err(Error::new_nomsg(1));
     }
 
     // Parse opts
-    let mut opts = Options::new(args.iter().skip(1).map(String::as_str));
-    while let Some(opt) = opts.next_opt().expect("argument parsing error") {
+    let mut opts = ArgParser::new(&SPEC, args);
+    let mut action: Option<&str> = None;
+    let mut install_options = Options::default();
+    let mut install_option_given = false;
+    while let Some(opt) = opts.next_opt()? {
         match opt {
-            Opt::Short('h') | Opt::Long("help") => {
-                eprintln!(
-                    "Usage: {} [--version|-v] [--install [basedir]] [--list-tools] [-h|--help] | [utility] ...",
-                    args[0]
-                );
-                return Ok(());
-            }
             Opt::Long("list-tools") => {
-                eprintln!("# Note: all tool paths are relative");
+                writeln!(ctx.stderr, "# Note: all tool paths are relative")?;
                 let utils = paths();
                 for (util_name, util_path) in utils {
-                    println!("{util_name} = {util_path}");
+                    writeln!(ctx.stdout, "{util_name} = {util_path}")?;
                 }
                 return Ok(());
             }
+            Opt::Long("man") => {
+                let name = opts.value();
+                let Some((path, _, spec)) = DISPATCH_TABLE.get(name).copied() else {
+                    return Err(Error::new(1, format!("{name}: utility not found")));
+                };
+                write_man(ctx.stdout, name, path, spec)?;
+                return Ok(());
+            }
+            Opt::Long("completions") => {
+                let shell = opts.value();
+                let Some(shell) = Shell::from_name(shell) else {
+                    return Err(opts.usage_error(&format!("unknown shell '{shell}'")));
+                };
+                write_completions(ctx.stdout, shell)?;
+                return Ok(());
+            }
             Opt::Short('v') | Opt::Long("version") => {
-                about(true);
+                about(ctx.stderr, true)?;
                 return Ok(());
             }
-            Opt::Long("install") => {
-                let prefix = opts.value().unwrap_or("");
-                return perform(prefix);
+            Opt::Long(name @ ("install" | "uninstall")) => {
+                if action.is_some() && action != Some(name) {
+                    return Err(opts.usage_error("--install and --uninstall can't be combined"));
+                }
+                action = Some(name);
+            }
+            Opt::Long(name) => {
+                install_option_given = true;
+                match name {
+                    "dry-run" => install_options.dry_run = true,
+                    "force" => install_options.force = true,
+                    "hardlinks" => install_options.hardlinks = true,
+                    "usr-merged" => install_options.usr_merged = true,
+                    _ => unreachable!(),
+                }
             }
-            _ => {}
+            Opt::Short(_) => unreachable!(),
+        }
+    }
+
+    match action {
+        Some("install") => {
+            let prefix = opts.positionals().next().unwrap_or("");
+            return perform(ctx, prefix, &install_options);
         }
+        Some("uninstall") => {
+            let prefix = opts.positionals().next().unwrap_or("");
+            return uninstall(ctx, prefix, &install_options);
+        }
+        _ if install_option_given => {
+            return Err(opts.usage_error("option only valid with --install or --uninstall"));
+        }
+        _ => {}
     }
 
+    // Skip past -- if it was given
+    let rest = opts.positionals().count();
+    if rest == 0 {
+        return Err(opts.usage_error("no utility specified"));
+    }
+    let args = &args[args.len() - rest..];
+
     // Determine if what we're executing is a builtin
     // If it is, run it and leave.
-    if let Some(util_entry) = DISPATCH_TABLE.get(&args[1]).copied() {
-        return util_entry.1(&args[1..]);
+    if let Some(result) = DISPATCH_TABLE.run(ctx, &args[0], args) {
+        return result;
     }
 
-    let status = Command::new(&args[1]).args(args.iter().skip(1)).status();
-    match status {
-        Ok(status) => status.code().map_or_else(
-            || Err(Error::new(255, "Process terminated by signal".to_string())),
-            |code| Err(Error::new(code, format!("Exited with status code {code}"))),
-        ),
-        Err(e) => Err(Error::new(255, format!("Could not execute command: {e}"))),
+    // The command speaks for itself, we only pass its status on
+    let status = ctx
+        .run_command(Command::new(&args[0]).args(args.iter().skip(1)))
+        .map_err(|e| Error::exec(&args[0], e))?;
+    match status.code() {
+        Some(0) => Ok(()),
+        Some(code) => Err(Error::new_nomsg(code)),
+        None => Err(Error::new(255, "Process terminated by signal".to_string())),
     }
 }
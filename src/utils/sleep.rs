@@ -6,12 +6,17 @@
 use std::thread::sleep;
 use std::time::Duration;
 
-use crate::err::{Error, Result};
-
-fn usage(arg0: &str) -> Error {
-    eprintln!("Usage: {arg0} seconds");
-    Error::new_nomsg(1)
-}
+use crate::argspec::{ArgParser, ArgSpec};
+use crate::context::Context;
+use crate::err::Result;
+
+pub const SPEC: ArgSpec = ArgSpec {
+    about: "Suspend execution for an interval of time.",
+    operands: &["seconds"],
+    options: &[],
+    exclusive: &[],
+    raw_args: false,
+};
 
 #[cfg(not(target_os = "windows"))]
 fn block_sigalrm() {
@@ -24,20 +29,30 @@ fn block_sigalrm() {
 #[cfg(target_os = "windows")]
 const fn block_sigalrm() {}
 
-pub fn util(args: &[String]) -> Result {
+pub fn util(_ctx: &mut Context, args: &[String]) -> Result {
     block_sigalrm(); // POSIX sez this is a valid option
 
-    let arg = args.get(1).ok_or_else(|| usage(&args[0]))?;
+    let mut opts = ArgParser::new(&SPEC, args);
+    opts.no_opts()?;
+
+    let arg = opts
+        .positionals()
+        .next()
+        .ok_or_else(|| opts.usage_error("missing operand"))?;
 
     if arg.starts_with('-') {
-        return Err(usage(&args[0]));
+        return Err(opts.usage_error(&format!("invalid time interval '{arg}'")));
     }
 
     // We check the sign above, so we don't care.
     // We also don't have a better method to convert, unless we use nightly, so meh.
     #[allow(clippy::cast_sign_loss)]
     #[allow(clippy::cast_possible_truncation)]
-    let sleep_nsec = (arg.parse::<f32>().map_err(|_| usage(&args[0]))? * 1e9).round() as u64;
+    let sleep_nsec = (arg
+        .parse::<f32>()
+        .map_err(|_| opts.usage_error(&format!("invalid time interval '{arg}'")))?
+        * 1e9)
+        .round() as u64;
 
     sleep(Duration::from_nanos(sleep_nsec));
 
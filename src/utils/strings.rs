@@ -1,22 +1,23 @@
-/* utils/wc.rs - implementation of wc
+/* utils/strings.rs - implementation of strings
  * Copyright (C) 2023 Elizabeth Myers. All rights reserved.
  * SPDX-License-Identifier: GPL-2.0-only
  */
 
+mod object;
+
 use std::fs::File;
-use std::io;
 use std::io::prelude::*;
+use std::io::{self, BufReader, SeekFrom};
+use std::mem;
 use std::str::FromStr;
 
-use getargs::{Opt, Options};
+use getargs::Opt;
 
-use crate::bufinput::BufInput;
+use crate::argspec::{ArgParser, ArgSpec, OptSpec};
+use crate::bufinput::Operands;
+use crate::context::Context;
 use crate::err::{Error, Result};
 
-// 4 blocks on an AF disk at a time, or 32 blocks on a traditional disk
-// Good enough for anyone.
-const BUFFSIZE: usize = 16384usize;
-
 #[derive(PartialEq, Eq)]
 pub enum FlagsOffsetType {
     NoneType,
@@ -25,10 +26,86 @@ pub enum FlagsOffsetType {
     Dec,
 }
 
+pub const SPEC: ArgSpec = ArgSpec {
+    about: "Print the sequences of printable characters in files.",
+    operands: &["[file...]"],
+    options: &[
+        OptSpec::short(
+            'a',
+            "scan the entire file, not just the data in object files",
+        )
+        .alias("all"),
+        OptSpec::short(
+            'e',
+            "characters are encoding: s (7-bit, default), S (8-bit), b or l (16-bit big or little \
+             endian), or B or L (32-bit big or little endian)",
+        )
+        .alias("encoding")
+        .value("encoding"),
+        OptSpec::short('f', "print the name of the file before each string")
+            .alias("print-file-name"),
+        OptSpec::short(
+            'n',
+            "print sequences of at least number characters (default 4)",
+        )
+        .alias("bytes")
+        .value("number"),
+        OptSpec::short('t', "print the offset of each string in radix d, o, or x")
+            .alias("radix")
+            .value("format"),
+        OptSpec::short(
+            'w',
+            "count all whitespace as printable, not just spaces and tabs",
+        )
+        .alias("include-all-whitespace"),
+    ],
+    exclusive: &[],
+    raw_args: false,
+};
+
+// How characters are stored
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum FlagsEncoding {
+    // Single bytes, ASCII only
+    SevenBit,
+    // Single bytes, with anything over 127 counting as printable
+    EightBit,
+    // UTF-16 and UTF-32 style units, of which only the ones under 256 can be printable
+    BigEndian16,
+    LittleEndian16,
+    BigEndian32,
+    LittleEndian32,
+}
+
+impl FlagsEncoding {
+    // How many bytes make a character
+    const fn width(self) -> usize {
+        match self {
+            Self::SevenBit | Self::EightBit => 1,
+            Self::BigEndian16 | Self::LittleEndian16 => 2,
+            Self::BigEndian32 | Self::LittleEndian32 => 4,
+        }
+    }
+
+    fn decode(self, bytes: &[u8]) -> u32 {
+        match self {
+            Self::SevenBit | Self::EightBit => u32::from(bytes[0]),
+            Self::BigEndian16 => u32::from(u16::from_be_bytes([bytes[0], bytes[1]])),
+            Self::LittleEndian16 => u32::from(u16::from_le_bytes([bytes[0], bytes[1]])),
+            Self::BigEndian32 => u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            Self::LittleEndian32 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        }
+    }
+}
+
 // Flags
 pub struct Flags {
     pub offset_type: FlagsOffsetType,
     pub min_len: u64,
+    pub all: bool,
+    pub encoding: FlagsEncoding,
+    pub print_names: bool,
+    pub whitespace: bool,
 }
 
 impl Flags {
@@ -37,156 +114,321 @@ impl Flags {
         Self {
             offset_type: FlagsOffsetType::NoneType,
             min_len: 4u64,
+            all: false,
+            encoding: FlagsEncoding::SevenBit,
+            print_names: false,
+            whitespace: false,
         }
     }
+
+    /* What binutils and everyone else counts as printable: ASCII graphic characters, space, and
+     * tab, plus the rest of the whitespace with -w, and anything with the top bit set with -e S.
+     * Whatever the encoding, a printable character fits in a byte, and that byte is what's printed.
+     * --Elizafox
+     */
+    fn printable(&self, c: u32) -> Option<u8> {
+        let byte = u8::try_from(c).ok()?;
+        let printable = byte == b'\t'
+            || (b' '..=b'~').contains(&byte)
+            || (self.encoding == FlagsEncoding::EightBit && byte > 127)
+            || (self.whitespace && matches!(byte, b'\n' | b'\x0b' | b'\x0c' | b'\r'));
+        printable.then_some(byte)
+    }
 }
 
-fn read_file(reader: &mut BufInput, flags: &Flags) -> io::Result<()> {
-    let mut buffer = [0u8; BUFFSIZE];
-    let mut start = 0usize;
-    let mut has_eof = false;
-    while !has_eof || start > 0 {
-        let len = if !has_eof && start < buffer.len() {
-            // Ingest more data
-            let rlen = reader.read(&mut buffer[start..])?;
-            if rlen == 0 {
-                has_eof = true;
-
-                if start > 0 {
-                    // We still have characters in the buffer.
-                    // We should parse them.
-                    start
-                } else {
-                    // EOF reached
-                    continue;
-                }
-            } else {
-                // Not EOF, combine starting position and length
-                rlen + start
-            }
+/* Runs of printable characters are found a buffer at a time, so the scanner has to remember where
+ * it was when a buffer runs out: a run can start in one buffer and end several buffers later, and
+ * with -e, a character can be split between two of them. A run is held back until it's long enough
+ * to print; after that, the rest of it goes straight out, so we never need more memory than -n
+ * says, however long the run.
+ *
+ * Like binutils, after a character that isn't printable, we carry on from the byte after the one
+ * it started at, not after the whole character. That way UTF-16 strings are found wherever they
+ * start, odd offset or not.
+ * --Elizafox
+ */
+struct Scanner<'f> {
+    flags: &'f Flags,
+    // Printed before each string with -f
+    name: &'f str,
+    // Offset in the file of the next byte to be scanned
+    offset: u64,
+    // Offset of the start of the current run
+    start: u64,
+    // The current run, while it's too short to print
+    run: Vec<u8>,
+    // Whether the current run is long enough, and is being printed
+    printing: bool,
+    // The start of a character cut off at the end of the last buffer
+    partial: Vec<u8>,
+}
+
+impl<'f> Scanner<'f> {
+    // Start scanning name at offset in the file
+    const fn new(flags: &'f Flags, name: &'f str, offset: u64) -> Self {
+        Self {
+            flags,
+            name,
+            offset,
+            start: 0,
+            run: Vec::new(),
+            printing: false,
+            partial: Vec::new(),
+        }
+    }
+
+    fn scan(&mut self, out: &mut dyn Write, data: &[u8]) -> io::Result<()> {
+        if self.flags.encoding.width() == 1 {
+            self.scan_bytes(out, data)
         } else {
-            // Process the string, do not ingest more data.
-            start
-        };
+            self.scan_wide(out, data)
+        }
+    }
 
-        let str_data;
-        match std::str::from_utf8(&buffer[..len]) {
-            Ok(data) => {
-                // Valid string, good to go
-                str_data = data.to_string();
-                start = 0;
+    // Characters are bytes, so runs can be copied out as they are
+    fn scan_bytes(&mut self, out: &mut dyn Write, mut data: &[u8]) -> io::Result<()> {
+        while !data.is_empty() {
+            let len = data
+                .iter()
+                .position(|b| self.flags.printable(u32::from(*b)).is_none())
+                .unwrap_or(data.len());
+            self.extend(out, &data[..len], len)?;
+
+            if len < data.len() {
+                self.end(out)?;
+                self.offset += 1;
+                data = &data[len + 1..];
+            } else {
+                data = &[];
             }
-            Err(e) => {
-                // Hmm, seems we have a problem.
-                let valid_up_to = e.valid_up_to();
-                str_data =
-                    unsafe { std::str::from_utf8_unchecked(&buffer[..valid_up_to]) }.to_string();
-
-                if let Some(invalid_bytes) = e.error_len() {
-                    // Skip the bad bits.
-                    let skip = invalid_bytes + valid_up_to;
-                    buffer.copy_within(skip..len, 0);
-                    start = len - skip;
-                } else {
-                    // This is part of another char, just put it at the beginning.
-                    buffer.copy_within(valid_up_to..len, 0);
-                    start = len - valid_up_to;
-                }
+        }
+
+        Ok(())
+    }
+
+    fn scan_wide(&mut self, out: &mut dyn Write, data: &[u8]) -> io::Result<()> {
+        let width = self.flags.encoding.width();
+        let mut joined = mem::take(&mut self.partial);
+        joined.extend_from_slice(data);
+
+        let mut chars = Vec::new();
+        let mut i = 0;
+        while i + width <= joined.len() {
+            let c = self.flags.encoding.decode(&joined[i..i + width]);
+            if let Some(byte) = self.flags.printable(c) {
+                chars.push(byte);
+                i += width;
+            } else {
+                self.extend(out, &chars, chars.len() * width)?;
+                chars.clear();
+                self.end(out)?;
+                self.offset += 1;
+                i += 1;
             }
         }
 
-        if (str_data.len() as u64) < flags.min_len {
-            // Insufficient data to even bother with the below.
-            continue;
+        self.extend(out, &chars, chars.len() * width)?;
+        joined.drain(..i);
+        self.partial = joined;
+        Ok(())
+    }
+
+    // Add printable characters, which took up len bytes of the file, to the current run
+    fn extend(&mut self, out: &mut dyn Write, chars: &[u8], len: usize) -> io::Result<()> {
+        if chars.is_empty() {
+            return Ok(());
+        }
+
+        if self.run.is_empty() && !self.printing {
+            self.start = self.offset;
         }
+        self.offset += len as u64;
 
-        for string in str_data.split_whitespace() {
-            let string = string
-                .escape_default()
-                .collect::<String>();
-            if (string.len() as u64) > flags.min_len {
-                println!("{str_data}");
-            }
+        if self.printing {
+            return out.write_all(chars);
         }
+
+        self.run.extend_from_slice(chars);
+        if (self.run.len() as u64) < self.flags.min_len {
+            return Ok(());
+        }
+
+        if self.flags.print_names {
+            write!(out, "{}: ", self.name)?;
+        }
+        match self.flags.offset_type {
+            FlagsOffsetType::NoneType => {}
+            FlagsOffsetType::Dec => write!(out, "{:7} ", self.start)?,
+            FlagsOffsetType::Octal => write!(out, "{:7o} ", self.start)?,
+            FlagsOffsetType::Hex => write!(out, "{:7x} ", self.start)?,
+        }
+        out.write_all(&self.run)?;
+        self.run.clear();
+        self.printing = true;
+        Ok(())
     }
 
-    Ok(())
+    // The current run is over, whether or not it was long enough
+    fn end(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        self.run.clear();
+        if self.printing {
+            self.printing = false;
+            writeln!(out)?;
+        }
+
+        Ok(())
+    }
 }
 
-fn usage(arg0: &str) {
-    eprintln!("Usage: {arg0} [-a] [-n] [-t type] [file...]");
+fn scan_input(
+    out: &mut dyn Write,
+    reader: &mut dyn BufRead,
+    flags: &Flags,
+    name: &str,
+    offset: u64,
+) -> io::Result<()> {
+    let mut scanner = Scanner::new(flags, name, offset);
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            break;
+        }
+
+        let len = buffer.len();
+        scanner.scan(out, buffer)?;
+        reader.consume(len);
+    }
+
+    scanner.end(out)
 }
 
-pub fn util(args: &[String]) -> Result {
+// Strings never run from one section into the next, even if they're right next to each other
+fn scan_file(out: &mut dyn Write, mut file: File, flags: &Flags, name: &str) -> io::Result<()> {
+    if !flags.all && file.metadata()?.is_file() {
+        if let Some(sections) = object::data_sections(&mut file)? {
+            for section in sections {
+                file.seek(SeekFrom::Start(section.start))?;
+                let mut reader = BufReader::new((&file).take(section.end - section.start));
+                scan_input(out, &mut reader, flags, name, section.start)?;
+            }
+
+            return Ok(());
+        }
+
+        file.rewind()?;
+    }
+
+    scan_input(out, &mut BufReader::new(file), flags, name, 0)
+}
+
+pub fn util(ctx: &mut Context, args: &[String]) -> Result {
     let mut flags = Flags::new();
 
-    let mut opts = Options::new(args.iter().skip(1).map(String::as_str));
-    while let Some(opt) = opts.next_opt().expect("argument parsing error") {
+    let mut opts = ArgParser::new(&SPEC, args);
+    while let Some(opt) = opts.next_opt()? {
         match opt {
-            Opt::Short('n') => {
-                let Ok(arg) = opts.value() else { return Err(Error::new(1, "-n must have a total".to_string())) };
-                match u64::from_str(arg) {
-                    Ok(result) => {
-                        if result == 0 {
-                            return Err(Error::new(1, "-n: Total cannot be zero".to_string()));
-                        }
-                        flags.min_len = result;
-                    }
-                    Err(e) => {
-                        return Err(Error::new(1, format!("-n: Invalid total: {e}")));
+            Opt::Short('a') => flags.all = true,
+            Opt::Short('e') => {
+                let arg = opts.value();
+                flags.encoding = match arg {
+                    "s" => FlagsEncoding::SevenBit,
+                    "S" => FlagsEncoding::EightBit,
+                    "b" => FlagsEncoding::BigEndian16,
+                    "l" => FlagsEncoding::LittleEndian16,
+                    "B" => FlagsEncoding::BigEndian32,
+                    "L" => FlagsEncoding::LittleEndian32,
+                    _ => {
+                        return Err(opts.usage_error(&format!("invalid encoding: '{arg}'")));
                     }
-                }
+                };
             }
+            Opt::Short('f') => flags.print_names = true,
+            Opt::Short('n') => match u64::from_str(opts.value()) {
+                Ok(0) | Err(_) => {
+                    return Err(opts.usage_error(&format!(
+                        "invalid minimum string length: '{}'",
+                        opts.value()
+                    )));
+                }
+                Ok(result) => flags.min_len = result,
+            },
             Opt::Short('t') => {
-                let Ok(arg) = opts.value() else { return Err(Error::new(1, "-t must have an argument".to_string())) };
+                let arg = opts.value();
                 flags.offset_type = match arg {
                     "d" => FlagsOffsetType::Dec,
                     "o" => FlagsOffsetType::Octal,
                     "x" => FlagsOffsetType::Hex,
                     _ => {
-                        return Err(Error::new(1, format!("-t: invalid format specifier {arg}")));
+                        return Err(opts.usage_error(&format!("invalid radix: '{arg}'")));
                     }
                 };
             }
-            Opt::Short('h') | Opt::Long("help") => {
-                usage(&args[0]);
-                return Ok(());
-            }
-            _ => {}
+            Opt::Short('w') => flags.whitespace = true,
+            _ => unreachable!(),
         }
     }
 
-    let mut files: Vec<(&str, BufInput)> = Vec::new();
-
-    for filename in opts.positionals() {
-        if filename == "-" {
-            files.push(("stdin", BufInput::Standard(io::stdin().lock())));
-        } else {
-            let file = File::open(filename);
-            match file {
-                Ok(file) => {
-                    files.push((filename, BufInput::File(io::BufReader::new(file))));
-                }
-                Err(e) => {
-                    return Err(Error::new(
-                        1,
-                        format!("Could not open file: {filename}: {e}"),
-                    ));
-                }
-            }
+    let mut operands = Operands::new(opts.positionals());
+    while let Some(operand) = operands.open_next(ctx) {
+        let filename = operand.label();
+        let result = match operand.into_file() {
+            Some(file) => scan_file(ctx.stdout, file, &flags, filename),
+            None => scan_input(ctx.stdout, ctx.stdin, &flags, filename, 0),
+        };
+        if let Err(e) = result {
+            operands.report(ctx, &Error::io(filename, e));
         }
     }
 
-    if files.is_empty() {
-        // If ain't nobody got me, stdin got me.
-        files.push(("stdin", BufInput::Standard(io::stdin().lock())));
+    operands.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Flags, FlagsEncoding, FlagsOffsetType, Scanner};
+
+    fn scan(flags: &Flags, chunks: &[&[u8]]) -> String {
+        let mut out = Vec::new();
+        let mut scanner = Scanner::new(flags, "test", 0);
+        for chunk in chunks {
+            scanner.scan(&mut out, chunk).unwrap();
+        }
+        scanner.end(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
     }
 
-    for (filename, ref mut file) in &mut files {
-        if let Err(e) = read_file(file, &flags) {
-            eprintln!("{}: Could not read {filename}: {e}", args[0]);
+    #[test]
+    fn spans_buffers() {
+        let flags = Flags {
+            offset_type: FlagsOffsetType::Dec,
+            ..Flags::new()
+        };
+        let data = b"\x00abc\x01\x02longer one\x7fshort\x00xyz\x00\tend";
+        let whole = scan(&flags, &[data]);
+        assert_eq!(whole, "      6 longer one\n     17 short\n     27 \tend\n");
+
+        // Wherever the buffers happen to end, the output is the same
+        for split in 0..data.len() {
+            let (first, second) = data.split_at(split);
+            assert_eq!(scan(&flags, &[first, second]), whole, "split at {split}");
         }
     }
 
-    Ok(())
+    #[test]
+    fn utf16() {
+        let flags = Flags {
+            offset_type: FlagsOffsetType::Dec,
+            encoding: FlagsEncoding::LittleEndian16,
+            ..Flags::new()
+        };
+        // Strings at an even offset, an odd one, and one that's too short
+        let data = b"w\0i\0d\0e\0\x01o\0d\0d\0 \0o\0n\0e\0\0\0n\0o\0\0\0";
+        let whole = scan(&flags, &[data]);
+        assert_eq!(whole, "      0 wide\n      9 odd one\n");
+
+        for split in 0..data.len() {
+            let (first, second) = data.split_at(split);
+            assert_eq!(scan(&flags, &[first, second]), whole, "split at {split}");
+        }
+    }
 }
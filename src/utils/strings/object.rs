@@ -0,0 +1,543 @@
+/* utils/strings/object.rs - finding the data in object files for strings
+ * Copyright (C) 2023 Elizabeth Myers. All rights reserved.
+ * SPDX-License-Identifier: GPL-2.0-only
+ */
+
+/* POSIX only wants strings to look at the initialized data of an object file unless -a is given,
+ * since the code, symbol tables, and debug info are mostly noise. We understand the formats anyone
+ * is likely to point us at: ELF (32 or 64 bit, either endianness), PE and COFF, and Mach-O (thin or
+ * fat). Only the headers and section tables get read; nothing is loaded or relocated.
+ *
+ * A section counts as data when it has contents in the file, ends up in memory when the program
+ * is loaded, and isn't code or debug info: .data, .rodata, and friends, or __DATA,__data and
+ * __TEXT,__cstring on Mach-O.
+ *
+ * Anything we don't recognise, or that's too mangled to make sense of, is scanned in full, just
+ * like -a. Better to print too much than to silently print nothing.
+ * --Elizafox
+ */
+
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Range;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Endian {
+    Little,
+    Big,
+}
+
+fn malformed() -> io::Error {
+    io::ErrorKind::InvalidData.into()
+}
+
+// Fields of headers, which may be cut short in a broken file
+impl Endian {
+    fn bytes<const N: usize>(data: &[u8], at: usize) -> io::Result<[u8; N]> {
+        data.get(at..at + N)
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(malformed)
+    }
+
+    fn u16(self, data: &[u8], at: usize) -> io::Result<u16> {
+        let bytes = Self::bytes(data, at)?;
+        Ok(match self {
+            Self::Little => u16::from_le_bytes(bytes),
+            Self::Big => u16::from_be_bytes(bytes),
+        })
+    }
+
+    fn u32(self, data: &[u8], at: usize) -> io::Result<u32> {
+        let bytes = Self::bytes(data, at)?;
+        Ok(match self {
+            Self::Little => u32::from_le_bytes(bytes),
+            Self::Big => u32::from_be_bytes(bytes),
+        })
+    }
+
+    fn u64(self, data: &[u8], at: usize) -> io::Result<u64> {
+        let bytes = Self::bytes(data, at)?;
+        Ok(match self {
+            Self::Little => u64::from_le_bytes(bytes),
+            Self::Big => u64::from_be_bytes(bytes),
+        })
+    }
+
+    // A 32-bit field in 32-bit objects, 64-bit in 64-bit ones
+    fn word(self, data: &[u8], at: usize, wide: bool) -> io::Result<u64> {
+        if wide {
+            self.u64(data, at)
+        } else {
+            self.u32(data, at).map(u64::from)
+        }
+    }
+}
+
+// Read exactly len bytes at offset; a short read means the file's been truncated
+fn read_at<R: Read + Seek>(file: &mut R, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(offset))?;
+    let mut data = Vec::new();
+    file.take(len).read_to_end(&mut data)?;
+    if (data.len() as u64) < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(data)
+}
+
+fn section(base: u64, offset: u64, size: u64) -> io::Result<Range<u64>> {
+    let start = base.checked_add(offset).ok_or_else(malformed)?;
+    let end = start.checked_add(size).ok_or_else(malformed)?;
+    Ok(start..end)
+}
+
+fn index(value: u64) -> io::Result<usize> {
+    usize::try_from(value).map_err(|_| malformed())
+}
+
+const SHT_NOBITS: u32 = 8;
+const SHF_ALLOC: u64 = 0x2;
+const SHF_EXECINSTR: u64 = 0x4;
+
+fn elf<R: Read + Seek>(file: &mut R, header: &[u8]) -> io::Result<Vec<Range<u64>>> {
+    let wide = match header.get(4) {
+        Some(1) => false,
+        Some(2) => true,
+        _ => return Err(malformed()),
+    };
+    let endian = match header.get(5) {
+        Some(1) => Endian::Little,
+        Some(2) => Endian::Big,
+        _ => return Err(malformed()),
+    };
+
+    let (shoff, shentsize, mut shnum) = if wide {
+        (
+            endian.u64(header, 0x28)?,
+            endian.u16(header, 0x3a)?,
+            endian.u16(header, 0x3c)?.into(),
+        )
+    } else {
+        (
+            endian.u32(header, 0x20)?.into(),
+            endian.u16(header, 0x2e)?,
+            u64::from(endian.u16(header, 0x30)?),
+        )
+    };
+    if shoff == 0 {
+        // No section table, so no way of telling what's what
+        return Err(malformed());
+    }
+
+    let (size_at, entry_len) = if wide { (0x20, 0x40) } else { (0x14, 0x28) };
+    if usize::from(shentsize) < entry_len {
+        return Err(malformed());
+    }
+
+    // Too many sections to count in the header; the real number is in the first one's size
+    if shnum == 0 {
+        let first = read_at(file, shoff, u64::from(shentsize))?;
+        shnum = endian.word(&first, size_at, wide)?;
+    }
+
+    let len = shnum
+        .checked_mul(u64::from(shentsize))
+        .ok_or_else(malformed)?;
+    let table = read_at(file, shoff, len)?;
+
+    let mut sections = Vec::new();
+    for entry in table.chunks_exact(usize::from(shentsize)) {
+        let kind = endian.u32(entry, 0x4)?;
+        let flags = endian.word(entry, 0x8, wide)?;
+        let (offset, size) = if wide {
+            (endian.u64(entry, 0x18)?, endian.u64(entry, 0x20)?)
+        } else {
+            (
+                endian.u32(entry, 0x10)?.into(),
+                endian.u32(entry, 0x14)?.into(),
+            )
+        };
+
+        if kind != 0 && kind != SHT_NOBITS && flags & SHF_ALLOC != 0 && flags & SHF_EXECINSTR == 0 {
+            sections.push(section(0, offset, size)?);
+        }
+    }
+
+    Ok(sections)
+}
+
+const IMAGE_FILE_MACHINE_I386: u16 = 0x14c;
+const IMAGE_FILE_MACHINE_ARMNT: u16 = 0x1c4;
+const IMAGE_FILE_MACHINE_AMD64: u16 = 0x8664;
+const IMAGE_FILE_MACHINE_ARM64: u16 = 0xaa64;
+const IMAGE_SCN_CNT_CODE: u32 = 0x20;
+const IMAGE_SCN_CNT_INITIALIZED_DATA: u32 = 0x40;
+const IMAGE_SCN_LNK_INFO: u32 = 0x200;
+const IMAGE_SCN_LNK_REMOVE: u32 = 0x800;
+
+// The COFF header at offset, as found in PE images and on its own in object files
+fn coff<R: Read + Seek>(file: &mut R, offset: u64) -> io::Result<Vec<Range<u64>>> {
+    let endian = Endian::Little;
+    let header = read_at(file, offset, 20)?;
+    let count = endian.u16(&header, 2)?;
+    let optional_len = endian.u16(&header, 16)?;
+
+    let table = read_at(
+        file,
+        offset + 20 + u64::from(optional_len),
+        u64::from(count) * 40,
+    )?;
+
+    let mut sections = Vec::new();
+    for entry in table.chunks_exact(40) {
+        let size = endian.u32(entry, 16)?;
+        let offset = endian.u32(entry, 20)?;
+        let flags = endian.u32(entry, 36)?;
+
+        let data = flags & IMAGE_SCN_CNT_INITIALIZED_DATA != 0;
+        let unloaded =
+            flags & (IMAGE_SCN_CNT_CODE | IMAGE_SCN_LNK_INFO | IMAGE_SCN_LNK_REMOVE) != 0;
+        if data && !unloaded && offset != 0 {
+            sections.push(section(0, offset.into(), size.into())?);
+        }
+    }
+
+    Ok(sections)
+}
+
+fn pe<R: Read + Seek>(file: &mut R, header: &[u8]) -> io::Result<Vec<Range<u64>>> {
+    let pe_offset = u64::from(Endian::Little.u32(header, 0x3c)?);
+    if read_at(file, pe_offset, 4)? != b"PE\0\0" {
+        return Err(malformed());
+    }
+
+    coff(file, pe_offset + 4)
+}
+
+/* COFF object files don't have a magic number, just the machine type. We only believe it for the
+ * machines people actually use, and only with no optional header, which objects never have; that
+ * keeps us from mistaking some random file for one.
+ * --Elizafox
+ */
+fn is_coff_object(header: &[u8]) -> bool {
+    let machine = Endian::Little.u16(header, 0).unwrap_or(0);
+    let optional_len = Endian::Little.u16(header, 16).unwrap_or(u16::MAX);
+    matches!(
+        machine,
+        IMAGE_FILE_MACHINE_I386
+            | IMAGE_FILE_MACHINE_ARMNT
+            | IMAGE_FILE_MACHINE_AMD64
+            | IMAGE_FILE_MACHINE_ARM64
+    ) && optional_len == 0
+}
+
+const LC_SEGMENT: u32 = 0x1;
+const LC_SEGMENT_64: u32 = 0x19;
+const SECTION_TYPE: u32 = 0xff;
+const S_ZEROFILL: u32 = 0x1;
+const S_GB_ZEROFILL: u32 = 0xc;
+const S_THREAD_LOCAL_ZEROFILL: u32 = 0x12;
+const S_ATTR_PURE_INSTRUCTIONS: u32 = 0x8000_0000;
+const S_ATTR_DEBUG: u32 = 0x0200_0000;
+const S_ATTR_SOME_INSTRUCTIONS: u32 = 0x400;
+
+fn macho<R: Read + Seek>(
+    file: &mut R,
+    base: u64,
+    endian: Endian,
+    wide: bool,
+) -> io::Result<Vec<Range<u64>>> {
+    let header_len = if wide { 32 } else { 28 };
+    let header = read_at(file, base, header_len)?;
+    let ncmds = endian.u32(&header, 16)?;
+    let commands = read_at(file, base + header_len, endian.u32(&header, 20)?.into())?;
+
+    let (segment, segment_len, nsects_at, section_len) = if wide {
+        (LC_SEGMENT_64, 72, 64, 80)
+    } else {
+        (LC_SEGMENT, 56, 48, 68)
+    };
+
+    let mut sections = Vec::new();
+    let mut at = 0;
+    for _ in 0..ncmds {
+        let cmd = endian.u32(&commands, at)?;
+        let cmdsize = index(endian.u32(&commands, at + 4)?.into())?;
+        if cmdsize < 8 {
+            return Err(malformed());
+        }
+
+        if cmd == segment {
+            let nsects = index(endian.u32(&commands, at + nsects_at)?.into())?;
+            for i in 0..nsects {
+                let entry = at + segment_len + i * section_len;
+                let (size_at, offset_at) = if wide {
+                    (entry + 40, entry + 48)
+                } else {
+                    (entry + 36, entry + 40)
+                };
+                let size = endian.word(&commands, size_at, wide)?;
+                let offset = endian.u32(&commands, offset_at)?;
+                let flags = endian.u32(&commands, offset_at + 16)?;
+
+                let zerofill = matches!(
+                    flags & SECTION_TYPE,
+                    S_ZEROFILL | S_GB_ZEROFILL | S_THREAD_LOCAL_ZEROFILL
+                );
+                let code_or_debug = flags
+                    & (S_ATTR_PURE_INSTRUCTIONS | S_ATTR_SOME_INSTRUCTIONS | S_ATTR_DEBUG)
+                    != 0;
+                if offset != 0 && !zerofill && !code_or_debug {
+                    sections.push(section(base, offset.into(), size)?);
+                }
+            }
+        }
+
+        at += cmdsize;
+    }
+
+    Ok(sections)
+}
+
+/* Fat (universal) binaries have the same magic number as Java class files. Java puts its version
+ * where we'd find the number of architectures, and that's always been at least 45, whereas nobody
+ * has ever shipped a fat binary with more than a handful.
+ * --Elizafox
+ */
+const FAT_MAX_ARCHES: u32 = 30;
+
+fn fat<R: Read + Seek>(
+    file: &mut R,
+    header: &[u8],
+    wide: bool,
+) -> io::Result<Option<Vec<Range<u64>>>> {
+    let endian = Endian::Big;
+    let count = endian.u32(header, 4)?;
+    if count == 0 || count > FAT_MAX_ARCHES {
+        return Ok(None);
+    }
+
+    let entry_len = if wide { 32 } else { 20 };
+    let table = read_at(file, 8, u64::from(count) * entry_len)?;
+
+    let mut sections = Vec::new();
+    for entry in table.chunks_exact(index(entry_len)?) {
+        let offset = endian.word(entry, 8, wide)?;
+        let magic = read_at(file, offset, 4)?;
+        match Endian::Little.u32(&magic, 0)? {
+            0xfeed_face => sections.extend(macho(file, offset, Endian::Little, false)?),
+            0xfeed_facf => sections.extend(macho(file, offset, Endian::Little, true)?),
+            0xcefa_edfe => sections.extend(macho(file, offset, Endian::Big, false)?),
+            0xcffa_edfe => sections.extend(macho(file, offset, Endian::Big, true)?),
+            _ => return Err(malformed()),
+        }
+    }
+
+    Ok(Some(sections))
+}
+
+fn parse<R: Read + Seek>(file: &mut R) -> io::Result<Option<Vec<Range<u64>>>> {
+    // Enough for the ELF header, and the start of the DOS one
+    let mut header = Vec::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.take(64).read_to_end(&mut header)?;
+
+    let sections = match header.get(..4) {
+        Some(b"\x7fELF") => elf(file, &header)?,
+        Some([b'M', b'Z', ..]) => pe(file, &header)?,
+        Some(b"\xfe\xed\xfa\xce") => macho(file, 0, Endian::Big, false)?,
+        Some(b"\xfe\xed\xfa\xcf") => macho(file, 0, Endian::Big, true)?,
+        Some(b"\xce\xfa\xed\xfe") => macho(file, 0, Endian::Little, false)?,
+        Some(b"\xcf\xfa\xed\xfe") => macho(file, 0, Endian::Little, true)?,
+        Some(b"\xca\xfe\xba\xbe") => return fat(file, &header, false),
+        Some(b"\xca\xfe\xba\xbf") => return fat(file, &header, true),
+        _ if is_coff_object(&header) => coff(file, 0)?,
+        _ => return Ok(None),
+    };
+
+    Ok(Some(sections))
+}
+
+// Where the initialized data is in file, in file order, or None if it should be scanned in full
+pub fn data_sections<R: Read + Seek>(file: &mut R) -> io::Result<Option<Vec<Range<u64>>>> {
+    match parse(file) {
+        Ok(Some(mut sections)) => {
+            sections.sort_by_key(|s| s.start);
+            Ok(Some(sections))
+        }
+        Ok(None) => Ok(None),
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof
+            ) =>
+        {
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{data_sections, Endian};
+
+    // Just enough of an object file to have section tables in it
+    struct Image {
+        data: Vec<u8>,
+        endian: Endian,
+    }
+
+    impl Image {
+        const fn new(endian: Endian) -> Self {
+            Self {
+                data: Vec::new(),
+                endian,
+            }
+        }
+
+        fn pad(&mut self, len: usize) {
+            if self.data.len() < len {
+                self.data.resize(len, 0);
+            }
+        }
+
+        fn put(&mut self, at: usize, bytes: &[u8]) {
+            self.pad(at + bytes.len());
+            self.data[at..at + bytes.len()].copy_from_slice(bytes);
+        }
+
+        fn int(&mut self, at: usize, value: u64, len: usize) {
+            let bytes = match self.endian {
+                Endian::Little => value.to_le_bytes()[..len].to_vec(),
+                Endian::Big => value.to_be_bytes()[8 - len..].to_vec(),
+            };
+            self.put(at, &bytes);
+        }
+
+        fn sections(&self) -> Option<Vec<(u64, u64)>> {
+            data_sections(&mut Cursor::new(&self.data))
+                .unwrap()
+                .map(|sections| sections.into_iter().map(|s| (s.start, s.end)).collect())
+        }
+    }
+
+    fn elf(wide: bool, endian: Endian) -> Image {
+        let mut image = Image::new(endian);
+        let word = if wide { 8 } else { 4 };
+        image.put(0, b"\x7fELF");
+        image.put(
+            4,
+            &[
+                if wide { 2 } else { 1 },
+                if endian == Endian::Little { 1 } else { 2 },
+            ],
+        );
+
+        let (shoff_at, entsize_at, entsize) = if wide {
+            (0x28, 0x3a, 0x40)
+        } else {
+            (0x20, 0x2e, 0x28)
+        };
+        image.int(shoff_at, 0x100, word);
+        image.int(entsize_at, entsize, 2);
+        image.int(entsize_at + 2, 5, 2);
+
+        // Null, code, read-only data, bss, and comments
+        let sections = [
+            (0, 0, 0, 0),
+            (1, 0x6, 0x40, 0x10),
+            (1, 0x2, 0x50, 0x10),
+            (8, 0x3, 0x60, 0x10),
+            (1, 0, 0x60, 0x10),
+        ];
+        for (i, (kind, flags, offset, size)) in sections.into_iter().enumerate() {
+            let entry = 0x100 + i * usize::try_from(entsize).unwrap();
+            image.int(entry + 4, kind, 4);
+            image.int(entry + 8, flags, word);
+            let (offset_at, size_at) = if wide { (0x18, 0x20) } else { (0x10, 0x14) };
+            image.int(entry + offset_at, offset, word);
+            image.int(entry + size_at, size, word);
+        }
+
+        image.pad(0x100 + sections.len() * usize::try_from(entsize).unwrap());
+        image
+    }
+
+    #[test]
+    fn elf_sections() {
+        for wide in [false, true] {
+            for endian in [Endian::Little, Endian::Big] {
+                assert_eq!(elf(wide, endian).sections(), Some(vec![(0x50, 0x60)]));
+            }
+        }
+    }
+
+    fn macho(wide: bool, endian: Endian) -> Image {
+        let mut image = Image::new(endian);
+        let (magic, header_len, segment, segment_len, section_len) = if wide {
+            (0xfeed_facf, 32, 0x19, 72, 80)
+        } else {
+            (0xfeed_face, 28, 0x1, 56, 68)
+        };
+        image.int(0, magic, 4);
+        image.int(16, 1, 4);
+        image.int(20, segment_len + 3 * section_len, 4);
+
+        let command = usize::try_from(header_len).unwrap();
+        image.int(command, segment, 4);
+        image.int(command + 4, segment_len + 3 * section_len, 4);
+        image.int(command + if wide { 64 } else { 48 }, 3, 4);
+
+        // Code, C strings, and zero fill
+        let sections = [(0x200, 0x8000_0400), (0x210, 0x2), (0, 0x1)];
+        for (i, (offset, flags)) in sections.into_iter().enumerate() {
+            let entry = command + usize::try_from(segment_len + i as u64 * section_len).unwrap();
+            let (size_at, offset_at) = if wide { (40, 48) } else { (36, 40) };
+            image.int(entry + size_at, 0x10, if wide { 8 } else { 4 });
+            image.int(entry + offset_at, offset, 4);
+            image.int(entry + offset_at + 16, flags, 4);
+        }
+
+        image.pad(command + usize::try_from(segment_len + 3 * section_len).unwrap());
+        image
+    }
+
+    #[test]
+    fn macho_sections() {
+        for wide in [false, true] {
+            for endian in [Endian::Little, Endian::Big] {
+                assert_eq!(macho(wide, endian).sections(), Some(vec![(0x210, 0x220)]));
+            }
+        }
+    }
+
+    #[test]
+    fn fat_sections() {
+        let mut image = Image::new(Endian::Big);
+        image.int(0, 0xcafe_babe, 4);
+        image.int(4, 1, 4);
+        image.int(8 + 8, 0x1000, 4);
+        image.put(0x1000, &macho(true, Endian::Little).data);
+        assert_eq!(image.sections(), Some(vec![(0x1210, 0x1220)]));
+
+        // That's not a fat binary, that's Java
+        image.int(4, 52, 4);
+        assert_eq!(image.sections(), None);
+    }
+
+    #[test]
+    fn not_objects() {
+        assert_eq!(Image::new(Endian::Little).sections(), None);
+
+        let mut text = Image::new(Endian::Little);
+        text.put(0, b"just some text, nothing to see here\n");
+        assert_eq!(text.sections(), None);
+
+        // Cut off in the middle of the section table
+        let mut truncated = elf(true, Endian::Little);
+        truncated.data.truncate(0x120);
+        assert_eq!(truncated.sections(), None);
+    }
+}
@@ -4,183 +4,757 @@
  */
 
 use std::collections::VecDeque;
-use std::fs::File;
-use std::io::{self, BufRead, BufReader};
-use std::iter;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
 use std::path::Path;
 use std::str::FromStr;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
 use std::time::Duration;
 
-use getargs::{Opt, Options};
-use notify::event::EventKind::Modify;
+use getargs::Opt;
 use notify::{Config, RecommendedWatcher, RecursiveMode, Watcher, WatcherKind};
-use reverse_lines::ReverseLines;
 
-use crate::bufinput::BufInput;
+use crate::argspec::{ArgParser, ArgSpec, OptSpec};
+use crate::bufinput::Operands;
+use crate::context::Context;
 use crate::err::{Error, Result};
+use crate::platform::fileid::{file_id, FileId};
+use crate::platform::process::ProcessWatch;
 
-fn usage(arg0: &str) {
-    eprintln!("Usage: {arg0} [-n] lines [-h|--help] [FILE] ...");
+pub const SPEC: ArgSpec = ArgSpec {
+    about: "Print the last lines of each file.",
+    operands: &["[file...]"],
+    options: &[
+        OptSpec::short(
+            'c',
+            "print the last number bytes (+number: from byte number on)",
+        )
+        .alias("bytes")
+        .value("number"),
+        OptSpec::short(
+            'f',
+            "output appended data as the files grow; how is descriptor (default) or name",
+        )
+        .alias("follow")
+        .optional_value("how"),
+        OptSpec::short(
+            'F',
+            "follow by name, and keep retrying (--follow=name --retry)",
+        ),
+        OptSpec::short(
+            'n',
+            "print the last number lines (default 10; +number: from line number on)",
+        )
+        .alias("lines")
+        .value("number"),
+        OptSpec::long("pid", "with -f, stop once process pid has gone").value("pid"),
+        OptSpec::long("retry", "keep trying to open files that can't be opened"),
+        OptSpec::short(
+            's',
+            "with -f, look for changes every number seconds (default 1)",
+        )
+        .alias("sleep-interval")
+        .value("number"),
+        OptSpec::short('z', "lines end with NUL, not newline").alias("zero-terminated"),
+    ],
+    exclusive: &[&['c', 'n']],
+    raw_args: false,
+};
+
+const BUFFSIZE: usize = 16384usize;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Unit {
+    Bytes,
+    Lines,
 }
 
-fn follow(name: &str, total: usize) -> Result {
-    let path = Path::new(name);
-    let file = BufReader::new(
-        File::open(path).map_err(|e| Error::new(1, format!("Could not open file {name}: {e}")))?,
-    );
+// Where the output starts
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Start {
+    // This many units before the end
+    End(u64),
+    // At this unit, counting from 1 (+0 is the same as +1, like everyone else does it)
+    Beginning(u64),
+}
 
-    let config = if RecommendedWatcher::kind() == WatcherKind::PollWatcher {
-        Config::default()
-            .with_poll_interval(Duration::from_millis(500))
-            .with_compare_contents(true)
+#[derive(Copy, Clone, PartialEq, Eq)]
+struct Amount {
+    unit: Unit,
+    start: Start,
+    // What ends a line: a newline, or a NUL with -z
+    delimiter: u8,
+}
+
+// "N" and "-N" count from the end, "+N" from the beginning
+fn parse_start(value: &str) -> Option<Start> {
+    value
+        .strip_prefix('+')
+        .map_or_else(
+            || u64::from_str(value.strip_prefix('-').unwrap_or(value)).map(Start::End),
+            |number| u64::from_str(number).map(Start::Beginning),
+        )
+        .ok()
+}
+
+// Seconds for -s, which can have a fraction; anything over a century or so is silly, and can't be
+// made into a Duration at some point anyway
+fn parse_interval(value: &str) -> Option<Duration> {
+    f64::from_str(value)
+        .ok()
+        .filter(|secs| (0.0..=f64::from(u32::MAX)).contains(secs))
+        .map(Duration::from_secs_f64)
+}
+
+/* The historical forms (tail -20, tail +20, tail -5c, tail -3lf and so on) are still all over the
+ * place, even though POSIX has called them obsolescent for decades. Like everyone else, we only
+ * look for them in the first argument, and turn them into the modern options before parsing. The
+ * b suffix means 512-byte blocks.
+ * --Elizafox
+ */
+fn obsolete_args(args: &[String]) -> Option<Vec<String>> {
+    let arg = args.get(1)?;
+    let sign = arg.chars().next().filter(|c| *c == '-' || *c == '+')?;
+    let rest = &arg[1..];
+    let digits = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    if digits == 0 {
+        return None;
+    }
+
+    let number = u64::from_str(&rest[..digits]).ok()?;
+    let suffix = &rest[digits..];
+    let (unit, number, suffix) = match suffix.as_bytes().first() {
+        Some(b'b') => ("-c", number.checked_mul(512)?, &suffix[1..]),
+        Some(b'c') => ("-c", number, &suffix[1..]),
+        Some(b'l') => ("-n", number, &suffix[1..]),
+        _ => ("-n", number, suffix),
+    };
+
+    let follow = match suffix {
+        "" => false,
+        "f" => true,
+        _ => return None,
+    };
+
+    let mut new_args = vec![args[0].clone(), unit.to_string()];
+    new_args.push(if sign == '+' {
+        format!("+{number}")
     } else {
-        Config::default()
+        number.to_string()
+    });
+    if follow {
+        new_args.push("-f".to_string());
+    }
+    new_args.extend_from_slice(&args[2..]);
+    Some(new_args)
+}
+
+// Skip past the first count lines
+fn skip_lines(input: &mut dyn BufRead, mut count: u64, delimiter: u8) -> io::Result<()> {
+    while count > 0 {
+        let buffer = input.fill_buf()?;
+        if buffer.is_empty() {
+            break;
+        }
+
+        let used = buffer
+            .iter()
+            .position(|b| *b == delimiter)
+            .map_or(buffer.len(), |i| {
+                count -= 1;
+                i + 1
+            });
+        input.consume(used);
+    }
+
+    Ok(())
+}
+
+/* Find where the last count lines of a file start, by reading backwards from the end a block at a
+ * time. That way a multi-gigabyte log costs about as much as the lines we print from it. The very
+ * last byte never starts a line, delimiter or not, so it doesn't need looking at.
+ * --Elizafox
+ */
+fn last_lines_offset(mut file: &File, len: u64, count: u64, delimiter: u8) -> io::Result<u64> {
+    if count == 0 {
+        return Ok(len);
+    }
+
+    let mut buffer = vec![0u8; BUFFSIZE];
+    let mut end = len.saturating_sub(1);
+    let mut seen = 0u64;
+    while end > 0 {
+        let size = usize::try_from(end).map_or(BUFFSIZE, |end| end.min(BUFFSIZE));
+        let start = end - size as u64;
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut buffer[..size])?;
+
+        for (i, _) in buffer[..size]
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, b)| **b == delimiter)
+        {
+            seen += 1;
+            if seen == count {
+                return Ok(start + i as u64 + 1);
+            }
+        }
+
+        end = start;
+    }
+
+    Ok(0)
+}
+
+// Regular files can be seeked around in, so we go straight to where the output starts
+fn tail_file(out: &mut dyn Write, mut file: &File, amount: Amount) -> io::Result<()> {
+    let len = file.metadata()?.len();
+    let offset = match (amount.unit, amount.start) {
+        (Unit::Bytes, Start::End(count)) => len.saturating_sub(count),
+        (Unit::Bytes, Start::Beginning(count)) => count.saturating_sub(1).min(len),
+        (Unit::Lines, Start::End(count)) => last_lines_offset(file, len, count, amount.delimiter)?,
+        (Unit::Lines, Start::Beginning(_)) => {
+            file.rewind()?;
+            return tail_stream(out, &mut BufReader::new(file), amount);
+        }
     };
 
-    let (tx, rx) = std::sync::mpsc::channel();
-    let mut watcher = RecommendedWatcher::new(tx, config)
-        .map_err(|e| Error::new(1, format!("Could not watch file {name}: {e}")))?;
-
-    watcher
-        .watch(path.as_ref(), RecursiveMode::NonRecursive)
-        .map_err(|e| Error::new(1, format!("Could not watch file {name}: {e}")))?;
-
-    // Print initial lines
-    let mut line_iter = file.lines();
-    let mut buff = VecDeque::with_capacity(total);
-    line_iter
-        .by_ref()
-        .map(|l| add_line(&mut buff, l, total))
-        .collect::<Result<Vec<_>, Error>>()
-        .map(|_| ())?;
-
-    for line in buff {
-        println!("{line}");
-    }
-
-    for res in rx {
-        match res {
-            Ok(event) => {
-                if let Modify(_) = event.kind {
-                    let mut buff = VecDeque::new();
-                    line_iter
-                        .by_ref()
-                        .map(|l| add_line(&mut buff, l, total))
-                        .collect::<Result<Vec<_>, Error>>()
-                        .map(|_| ())?;
-
-                    for line in buff {
-                        println!("{line}");
-                    }
+    file.seek(SeekFrom::Start(offset))?;
+    io::copy(&mut file, out)?;
+    Ok(())
+}
+
+// Anything else has to be read from start to finish, keeping only what we might print
+fn tail_stream(out: &mut dyn Write, input: &mut dyn BufRead, amount: Amount) -> io::Result<()> {
+    match (amount.unit, amount.start) {
+        (Unit::Bytes, Start::End(count)) => {
+            let count = usize::try_from(count).unwrap_or(usize::MAX);
+            let mut keep = Vec::new();
+            loop {
+                let buffer = input.fill_buf()?;
+                if buffer.is_empty() {
+                    break;
+                }
+
+                keep.extend_from_slice(buffer);
+                let used = buffer.len();
+                input.consume(used);
+
+                // Don't shuffle everything down on every read
+                if keep.len() > count.saturating_mul(2).max(BUFFSIZE) {
+                    keep.drain(..keep.len() - count);
                 }
             }
-            Err(e) => return Err(Error::new(1, format!("Failed to watch file {name}: {e}"))),
+
+            out.write_all(&keep[keep.len().saturating_sub(count)..])
+        }
+        (Unit::Lines, Start::End(count)) => {
+            let mut lines = VecDeque::new();
+            loop {
+                let mut line = Vec::new();
+                if input.read_until(amount.delimiter, &mut line)? == 0 {
+                    break;
+                }
+
+                lines.push_back(line);
+                if lines.len() as u64 > count {
+                    lines.pop_front();
+                }
+            }
+
+            lines.iter().try_for_each(|line| out.write_all(line))
+        }
+        (Unit::Bytes, Start::Beginning(count)) => {
+            io::copy(&mut input.take(count.saturating_sub(1)), &mut io::sink())?;
+            io::copy(input, out).map(|_| ())
+        }
+        (Unit::Lines, Start::Beginning(count)) => {
+            skip_lines(input, count.saturating_sub(1), amount.delimiter)?;
+            io::copy(input, out).map(|_| ())
         }
     }
+}
 
-    Ok(())
+// "==> name <==" before output from a different file than last time, when there's more than one
+struct Headers {
+    enabled: bool,
+    last: Option<usize>,
 }
 
-fn open((name, total): (&str, usize)) -> Result<(BufInput, usize)> {
-    if name == "-" {
-        return Ok((BufInput::Standard(io::stdin().lock()), total));
+impl Headers {
+    fn switch(&mut self, out: &mut dyn Write, index: usize, label: &str) -> io::Result<()> {
+        if !self.enabled || self.last == Some(index) {
+            return Ok(());
+        }
+
+        if self.last.is_some() {
+            writeln!(out)?;
+        }
+        self.last = Some(index);
+        writeln!(out, "==> {label} <==")
     }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum FollowMode {
+    // Keep reading the file we opened, whatever happens to its name
+    Descriptor,
+    // Keep reading whatever file has the name
+    Name,
+}
 
-    let f =
-        File::open(name).map_err(|e| Error::new(1, format!("Failed to open file: {name}: {e}")))?;
+// A file being followed with -f, and how far into it we've got
+struct Followed<'n> {
+    index: usize,
+    name: &'n str,
+    file: Option<File>,
+    id: Option<FileId>,
+    offset: u64,
+}
 
-    let f = BufInput::File(BufReader::new(f));
+impl<'n> Followed<'n> {
+    fn new(index: usize, name: &'n str, mut file: Option<File>) -> io::Result<Self> {
+        let (id, offset) = match &mut file {
+            Some(file) => (Some(file_id(&file.metadata()?)), file.stream_position()?),
+            None => (None, 0),
+        };
 
-    Ok((f, total))
+        Ok(Self {
+            index,
+            name,
+            file,
+            id,
+            offset,
+        })
+    }
 }
 
-fn add_line(buff: &mut VecDeque<String>, line: Result<String, io::Error>, total: usize) -> Result {
-    let line = line.map_err(|e| Error::new(1, format!("Failed to fetch line: {e}")))?;
+// With --retry, the files we couldn't open might turn up later, so follow those too
+fn add_missing<'n>(followed: &mut Vec<Followed<'n>>, names: &[&'n str]) {
+    for (index, &name) in names.iter().enumerate() {
+        if name != "-" && !followed.iter().any(|f| f.index == index) {
+            followed.push(Followed {
+                index,
+                name,
+                file: None,
+                id: None,
+                offset: 0,
+            });
+        }
+    }
+
+    followed.sort_by_key(|f| f.index);
+}
+
+/* Print anything new in a followed file. Data is copied as it comes, so a line written in bits
+ * comes out in bits too, rather than sitting here until someone finishes it.
+ *
+ * If a regular file is now shorter than where we were, someone truncated it (logrotate's
+ * copytruncate, or a plain "> file"), and the new contents start back at the beginning.
+ * --Elizafox
+ */
+fn read_new(ctx: &mut Context, followed: &mut Followed, headers: &mut Headers) -> Result {
+    let name = followed.name;
+    let Some(file) = &mut followed.file else {
+        return Ok(());
+    };
+
+    let metadata = file.metadata().map_err(|e| Error::io(name, e))?;
+    if metadata.is_file() {
+        if metadata.len() < followed.offset {
+            ctx.report(&Error::new(1, format!("{name}: file truncated")));
+            file.rewind().map_err(|e| Error::io(name, e))?;
+            followed.offset = 0;
+        }
+
+        if metadata.len() == followed.offset {
+            return Ok(());
+        }
+    }
 
-    buff.push_back(line);
-    if buff.len() > total {
-        buff.pop_front();
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)
+        .map_err(|e| Error::io(name, e))?;
+    if data.is_empty() {
+        return Ok(());
     }
 
+    headers.switch(ctx.stdout, followed.index, name)?;
+    ctx.stdout.write_all(&data)?;
+    ctx.stdout.flush()?;
+    followed.offset += data.len() as u64;
     Ok(())
 }
 
-fn output((file, total): (BufInput, usize)) -> Result {
-    let mut buff = VecDeque::with_capacity(total);
+/* See whether a different file has turned up under the name, for -F and --retry. If so, finish off
+ * the old one (a rotated log often gets a few last lines after it's renamed), and start on the new
+ * one from the top. If nothing is there, keep trying with --retry, or give up on the name without.
+ *
+ * Returns whether the name is still worth following.
+ * --Elizafox
+ */
+fn reopen(
+    ctx: &mut Context,
+    followed: &mut Followed,
+    headers: &mut Headers,
+    retry: bool,
+) -> Result<bool> {
+    let name = followed.name;
+    match fs::metadata(name) {
+        Ok(metadata) if Some(file_id(&metadata)) == followed.id => Ok(true),
+        Ok(_) => {
+            // Try again next time round if it's not readable (yet)
+            let Ok((file, metadata)) = File::open(name).and_then(|f| {
+                let metadata = f.metadata()?;
+                Ok((f, metadata))
+            }) else {
+                return Ok(true);
+            };
 
-    if let BufInput::File(file) = file {
-        // Use the efficient way
-        let reverse_lines = ReverseLines::new(file)
-            .map_err(|e| Error::new(1, format!("Failed to get reverse iterator: {e}")))?;
+            let what = if followed.file.is_some() {
+                read_new(ctx, followed, headers)?;
+                "has been replaced"
+            } else {
+                "has appeared"
+            };
+            ctx.report(&Error::new(
+                1,
+                format!("'{name}' {what}; following new file"),
+            ));
 
-        reverse_lines
-            .take(total)
-            .collect::<Vec<_>>()
-            .into_iter()
-            .rev()
-            .map(|l| add_line(&mut buff, l, total))
-            .collect::<Result<Vec<_>, Error>>()
-            .map(|_| ())?;
-    } else {
-        file.lines()
-            .map(|l| add_line(&mut buff, l, total))
-            .collect::<Result<Vec<_>, Error>>()
-            .map(|_| ())?;
+            followed.file = Some(file);
+            followed.id = Some(file_id(&metadata));
+            followed.offset = 0;
+            Ok(true)
+        }
+        Err(e) => {
+            if followed.file.is_some() {
+                read_new(ctx, followed, headers)?;
+                ctx.report(&Error::from(e).context(&format!("'{name}' has become inaccessible")));
+                followed.file = None;
+                followed.id = None;
+            }
+
+            Ok(retry)
+        }
     }
+}
+
+// How -f was asked to behave
+struct Follow {
+    mode: FollowMode,
+    retry: bool,
+    // How often to look at the files even if nothing tells us to; NFS and friends never send events
+    interval: Duration,
+    // Stop once this process is gone (--pid)
+    process: Option<ProcessWatch>,
+}
 
-    for line in buff {
-        println!("{line}");
+/* Watch for changes to the files, and print whatever gets added to them. Following by name means
+ * watching the directories the files are in, so we hear about them being created and renamed.
+ *
+ * Events don't reliably say which file they're about, so on any event we look at all of them; it's
+ * only an fstat each. We also look every so often regardless, in case the events never come, or
+ * can't be had at all. That's all a watch would have got us anyway, so failing to set one up isn't
+ * an error.
+ *
+ * With --pid, we stop after the last look once the process has gone, so whatever it wrote on its
+ * way out still gets printed.
+ * --Elizafox
+ */
+fn follow(
+    ctx: &mut Context,
+    mut files: Vec<Followed>,
+    headers: &mut Headers,
+    how: &Follow,
+) -> Result {
+    if files.is_empty() {
+        return Ok(());
     }
 
-    Ok(())
+    // Without inotify and friends (or on NFS), notify polls, and looking at the metadata each time
+    // is plenty: read_new() goes by size anyway. It can hash the contents too, but on a big log
+    // that's reading the whole thing every interval.
+    let config = if RecommendedWatcher::kind() == WatcherKind::PollWatcher {
+        Config::default().with_poll_interval(how.interval)
+    } else {
+        Config::default()
+    };
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = RecommendedWatcher::new(tx, config).ok();
+    if let Some(watcher) = &mut watcher {
+        let mut paths = Vec::new();
+        for followed in &files {
+            let path = Path::new(followed.name);
+            let path = match (how.mode, path.parent()) {
+                (FollowMode::Descriptor, _) => path,
+                (FollowMode::Name, Some(parent)) if parent != Path::new("") => parent,
+                (FollowMode::Name, _) => Path::new("."),
+            };
+
+            if !paths.contains(&path) {
+                paths.push(path);
+                let _ = watcher.watch(path, RecursiveMode::NonRecursive);
+            }
+        }
+    }
+
+    loop {
+        match rx.recv_timeout(how.interval) {
+            Ok(Ok(_)) | Err(RecvTimeoutError::Timeout) => {}
+            Ok(Err(e)) => return Err(Error::new(1, format!("Could not watch: {e}"))),
+            // No watcher at all, so it's just us polling
+            Err(RecvTimeoutError::Disconnected) => thread::sleep(how.interval),
+        }
+
+        let finished = how.process.as_ref().map_or(false, |p| !p.is_alive());
+
+        let mut i = 0;
+        while i < files.len() {
+            let followed = &mut files[i];
+            let keep = if how.mode == FollowMode::Name || followed.file.is_none() {
+                reopen(ctx, followed, headers, how.retry)?
+            } else {
+                true
+            };
+
+            if keep {
+                read_new(ctx, followed, headers)?;
+                i += 1;
+            } else {
+                files.remove(i);
+            }
+        }
+
+        if finished {
+            return Ok(());
+        } else if files.is_empty() {
+            return Err(Error::new(1, "no files remaining".to_string()));
+        }
+    }
 }
 
-pub fn util(args: &[String]) -> Result {
-    let mut total = 10usize; // POSIX default
-    let mut opts = Options::new(args.iter().skip(1).map(String::as_str));
-    let mut do_stream = false;
+pub fn util(ctx: &mut Context, args: &[String]) -> Result {
+    let args = obsolete_args(args).unwrap_or_else(|| args.to_vec());
 
-    while let Some(opt) = opts.next_opt().expect("argument parsing error") {
+    // POSIX default
+    let mut amount = Amount {
+        unit: Unit::Lines,
+        start: Start::End(10),
+        delimiter: b'\n',
+    };
+    let mut opts = ArgParser::new(&SPEC, &args);
+    let mut follow_mode = None;
+    let mut retry = false;
+    let mut interval = Duration::from_secs(1);
+    let mut pid = None;
+
+    while let Some(opt) = opts.next_opt()? {
         match opt {
-            Opt::Short('f') => do_stream = true,
-            Opt::Short('n') => {
-                total = usize::from_str(
-                    opts.value()
-                        .map_err(|_| Error::new(1, "-n: no line count given".to_string()))?,
-                )
-                .map_err(|e| Error::new(1, format!("Invalid total: {e}")))?;
+            Opt::Short('f') => {
+                follow_mode = match opts.value_opt() {
+                    None | Some("descriptor") => Some(FollowMode::Descriptor),
+                    Some("name") => Some(FollowMode::Name),
+                    Some(how) => {
+                        return Err(opts.usage_error(&format!(
+                            "invalid argument '{how}' for --follow (descriptor or name)"
+                        )));
+                    }
+                };
+            }
+            Opt::Short('F') => {
+                follow_mode = Some(FollowMode::Name);
+                retry = true;
+            }
+            Opt::Long("retry") => retry = true,
+            Opt::Short('z') => amount.delimiter = b'\0',
+            Opt::Short('s') => {
+                interval = parse_interval(opts.value()).ok_or_else(|| {
+                    opts.usage_error(&format!("invalid number of seconds: '{}'", opts.value()))
+                })?;
+            }
+            Opt::Long("pid") => {
+                pid = u32::from_str(opts.value()).ok().filter(|pid| *pid > 0);
+                if pid.is_none() {
+                    return Err(opts.usage_error(&format!("invalid PID: '{}'", opts.value())));
+                }
             }
-            Opt::Short('h') | Opt::Long("help") => {
-                usage(&args[0]);
-                return Ok(());
+            Opt::Short(c @ ('c' | 'n')) => {
+                let (unit, what) = if c == 'c' {
+                    (Unit::Bytes, "bytes")
+                } else {
+                    (Unit::Lines, "lines")
+                };
+                let start = parse_start(opts.value()).ok_or_else(|| {
+                    opts.usage_error(&format!("invalid number of {what}: '{}'", opts.value()))
+                })?;
+                amount.unit = unit;
+                amount.start = start;
             }
-            _ => {}
+            _ => unreachable!(),
         }
     }
 
-    if do_stream {
-        // We only care about the first argument in this case
-        if let Some(name) = opts.positionals().next() {
-            // POSIX sez we ignore -f for stdin
-            if name != "-" {
-                return follow(name, total);
+    let names: Vec<&str> = opts.positionals().collect();
+    let mut headers = Headers {
+        enabled: names.len() > 1,
+        last: None,
+    };
+
+    let mut followed = Vec::new();
+    let mut operands = Operands::new(names.clone());
+    while let Some(operand) = operands.open_next(ctx) {
+        let (name, index) = (operand.label(), operand.index);
+
+        headers.switch(ctx.stdout, index, name)?;
+        let result = match operand.into_file() {
+            Some(file) => if file.metadata().map_or(false, |m| m.is_file()) {
+                tail_file(ctx.stdout, &file, amount)
+            } else {
+                tail_stream(ctx.stdout, &mut BufReader::new(&file), amount)
             }
+            .map(|()| Some(file)),
+            None => tail_stream(ctx.stdout, ctx.stdin, amount).map(|()| None),
+        };
+
+        match result {
+            // POSIX sez we ignore -f for stdin
+            Ok(Some(file)) if follow_mode.is_some() => followed
+                .push(Followed::new(index, name, Some(file)).map_err(|e| Error::io(name, e))?),
+            Ok(_) => {}
+            Err(e) => operands.report(ctx, &Error::io(name, e)),
         }
     }
 
-    let files = opts
-        .positionals()
-        .zip(iter::repeat(total))
-        .map(open)
-        .collect::<Result<Vec<_>>>()?;
+    if let Some(mode) = follow_mode {
+        if retry {
+            add_missing(&mut followed, &names);
+        }
 
-    if files.is_empty() {
-        return output((BufInput::Standard(io::stdin().lock()), total));
+        let how = Follow {
+            mode,
+            retry,
+            interval,
+            process: pid.map(ProcessWatch::new),
+        };
+
+        ctx.stdout.flush()?;
+        follow(ctx, followed, &mut headers, &how)?;
     }
 
-    files
-        .into_iter()
-        .map(output)
-        .collect::<Result<Vec<_>>>()
-        .map(|_| ())
+    operands.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File, OpenOptions};
+    use std::io::{Seek, SeekFrom, Write};
+    use std::path::PathBuf;
+    use std::process;
+
+    use super::{read_new, reopen, Followed, Headers};
+    use crate::context::{Context, Env};
+
+    fn temp_path(what: &str) -> PathBuf {
+        env::temp_dir().join(format!("reutils-tail-{what}-{}", process::id()))
+    }
+
+    // Like the initial output has just been done: open, and at the end
+    fn followed(index: usize, name: &str) -> Followed<'_> {
+        let mut file = File::open(name).unwrap();
+        file.seek(SeekFrom::End(0)).unwrap();
+        Followed::new(index, name, Some(file)).unwrap()
+    }
+
+    const HEADERS: Headers = Headers {
+        enabled: false,
+        last: None,
+    };
+
+    #[test]
+    fn appends_and_truncation() {
+        let path = temp_path("truncate");
+        let name = path.to_str().unwrap();
+        fs::write(&path, "old\n").unwrap();
+        let mut followed = followed(0, name);
+        let mut headers = HEADERS;
+
+        let env = Env::new();
+        let (mut stdin, mut stdout, mut stderr) = (&b""[..], Vec::new(), Vec::new());
+        let mut ctx = Context::new(&mut stdin, &mut stdout, &mut stderr, &env);
+        ctx.set_arg0("tail");
+
+        let mut writer = OpenOptions::new().append(true).open(&path).unwrap();
+        writer.write_all(b"par").unwrap();
+        read_new(&mut ctx, &mut followed, &mut headers).unwrap();
+        writer.write_all(b"tial\n").unwrap();
+        read_new(&mut ctx, &mut followed, &mut headers).unwrap();
+        fs::write(&path, "new\n").unwrap();
+        read_new(&mut ctx, &mut followed, &mut headers).unwrap();
+        read_new(&mut ctx, &mut followed, &mut headers).unwrap();
+
+        drop(ctx);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(stdout, b"partial\nnew\n");
+        assert_eq!(
+            String::from_utf8(stderr).unwrap(),
+            format!("tail: {name}: file truncated\n")
+        );
+    }
+
+    #[test]
+    fn rotation() {
+        let path = temp_path("rotate");
+        let rotated = temp_path("rotated");
+        let name = path.to_str().unwrap();
+        fs::write(&path, "one\n").unwrap();
+        let mut followed = followed(0, name);
+        let mut headers = HEADERS;
+
+        let env = Env::new();
+        let (mut stdin, mut stdout, mut stderr) = (&b""[..], Vec::new(), Vec::new());
+        let mut ctx = Context::new(&mut stdin, &mut stdout, &mut stderr, &env);
+        ctx.set_arg0("tail");
+
+        // The old file gets one last line after it's moved out of the way
+        let mut writer = OpenOptions::new().append(true).open(&path).unwrap();
+        fs::rename(&path, &rotated).unwrap();
+        writer.write_all(b"two\n").unwrap();
+        assert!(reopen(&mut ctx, &mut followed, &mut headers, true).unwrap());
+        assert!(followed.file.is_none());
+        assert!(reopen(&mut ctx, &mut followed, &mut headers, true).unwrap());
+
+        fs::write(&path, "three\n").unwrap();
+        assert!(reopen(&mut ctx, &mut followed, &mut headers, true).unwrap());
+        read_new(&mut ctx, &mut followed, &mut headers).unwrap();
+
+        // Replaced without ever going missing
+        fs::rename(&path, &rotated).unwrap();
+        fs::write(&path, "four\n").unwrap();
+        assert!(reopen(&mut ctx, &mut followed, &mut headers, false).unwrap());
+        read_new(&mut ctx, &mut followed, &mut headers).unwrap();
+
+        // Without --retry, we give up when it goes
+        fs::remove_file(&path).unwrap();
+        assert!(!reopen(&mut ctx, &mut followed, &mut headers, false).unwrap());
+
+        drop(ctx);
+        fs::remove_file(&rotated).unwrap();
+        assert_eq!(stdout, b"two\nthree\nfour\n");
+        assert_eq!(
+            String::from_utf8(stderr).unwrap(),
+            format!(
+                "tail: '{name}' has become inaccessible: No such file or directory\n\
+                 tail: '{name}' has appeared; following new file\n\
+                 tail: '{name}' has been replaced; following new file\n\
+                 tail: '{name}' has become inaccessible: No such file or directory\n"
+            )
+        );
+    }
 }
@@ -4,73 +4,241 @@
  */
 
 use std::fs::File;
-use std::io::{stdin, stdout, Read, Write};
+use std::io::{self, Write};
 
-use getargs::{Opt, Options};
+use getargs::Opt;
 
-use crate::bufoutput::BufOutput;
+use crate::argspec::{ArgParser, ArgSpec, OptSpec};
+use crate::context::Context;
 use crate::err::{Error, Result};
-use crate::platform::signal::block_ctrlc;
+use crate::platform::signal::{block_ctrlc, ignore_sigpipe};
 
 const BUFFSIZE: usize = 16384usize;
 
+pub const SPEC: ArgSpec = ArgSpec {
+    about: "Copy standard input to each file, and also to standard output.",
+    operands: &["[file...]"],
+    options: &[
+        OptSpec::short('a', "append to the given files, do not overwrite").alias("append"),
+        OptSpec::short('i', "ignore interrupt signals").alias("ignore-interrupts"),
+        OptSpec::short(
+            'p',
+            "on output errors, do what mode says: warn, warn-nopipe (default), exit, or exit-nopipe",
+        )
+        .alias("output-error")
+        .optional_value("mode"),
+    ],
+    exclusive: &[],
+    raw_args: false,
+};
+
+// What to do when writing to an output fails
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum OutputError {
+    // Complain, drop the output, and keep going with the rest
+    Warn,
+    // Like Warn, but outputs that are closed pipes are dropped without a word
+    WarnNoPipe,
+    // Complain and stop
+    Exit,
+    // Like Exit, but closed pipes are dropped without a word, like WarnNoPipe
+    ExitNoPipe,
+}
+
+impl OutputError {
+    fn parse(mode: Option<&str>) -> Option<Self> {
+        match mode {
+            Some("warn") => Some(Self::Warn),
+            None | Some("warn-nopipe") => Some(Self::WarnNoPipe),
+            Some("exit") => Some(Self::Exit),
+            Some("exit-nopipe") => Some(Self::ExitNoPipe),
+            Some(_) => None,
+        }
+    }
+
+    const fn quiet_pipes(self) -> bool {
+        matches!(self, Self::WarnNoPipe | Self::ExitNoPipe)
+    }
+}
+
+// Somewhere to write; no file means stdout
+struct Output<'n> {
+    name: &'n str,
+    file: Option<File>,
+    // Cleared once writing to it has failed
+    open: bool,
+}
+
 fn block_sigint() {
     block_ctrlc();
 }
 
-fn usage(arg0: &str) {
-    eprintln!("Usage: {arg0} [-ai] [files]...");
+fn write_output(ctx: &mut Context, output: &mut Output, data: &[u8]) -> io::Result<()> {
+    match &mut output.file {
+        Some(file) => file.write_all(data),
+        None => ctx.stdout.write_all(data).and_then(|()| ctx.stdout.flush()),
+    }
 }
 
-pub fn util(args: &[String]) -> Result {
+// Returns whether the failure counts against the exit status
+fn output_failed(ctx: &mut Context, mode: OutputError, name: &str, e: io::Error) -> Result<bool> {
+    if mode.quiet_pipes() && e.kind() == io::ErrorKind::BrokenPipe {
+        return Ok(false);
+    }
+
+    let error = Error::io(name, e);
+    if matches!(mode, OutputError::Exit | OutputError::ExitNoPipe) {
+        return Err(error);
+    }
+
+    ctx.report(&error);
+    Ok(true)
+}
+
+fn open_output(filename: &str, do_append: bool) -> io::Result<File> {
+    if do_append {
+        File::options().read(false).append(true).open(filename)
+    } else {
+        File::create(filename)
+    }
+}
+
+/* POSIX says a tee that can't write to one of its outputs has to carry on writing to the others,
+ * and fail at the end. That's what we do, whatever the mode; the modes only decide whether a closed
+ * pipe is worth mentioning, and whether any failure should end things early. As a process, SIGPIPE
+ * is ignored, or the first reader to go away would kill us before the rest of the files got their
+ * data. Embedded, signal handling belongs to whoever's hosting us; Rust programs ignore SIGPIPE
+ * anyway, so a closed pipe comes back as EPIPE and is dealt with like any other write error.
+ * --Elizafox
+ */
+pub fn util(ctx: &mut Context, args: &[String]) -> Result {
     let mut do_append = false;
-    let mut opts = Options::new(args.iter().skip(1).map(String::as_str));
-    while let Some(opt) = opts.next_opt().expect("argument parsing error") {
+    let mut mode = OutputError::Warn;
+    let mut opts = ArgParser::new(&SPEC, args);
+    while let Some(opt) = opts.next_opt()? {
         match opt {
             Opt::Short('a') => do_append = true,
             Opt::Short('i') => block_sigint(),
-            Opt::Short('h') | Opt::Long("help") => {
-                usage(&args[0]);
-                return Ok(());
+            Opt::Short('p') => {
+                mode = OutputError::parse(opts.value_opt()).ok_or_else(|| {
+                    opts.usage_error(&format!(
+                        "invalid argument '{}' for --output-error \
+                         (warn, warn-nopipe, exit, or exit-nopipe)",
+                        opts.value_opt().unwrap_or_default()
+                    ))
+                })?;
             }
-            _ => {}
+            _ => unreachable!(),
         }
     }
 
-    let mut files: Vec<(&str, BufOutput)> = vec![("stdout", BufOutput::Standard(stdout().lock()))];
-
-    for filename in opts.positionals() {
-        let file = BufOutput::File(if do_append {
-            File::options()
-                .read(false)
-                .append(true)
-                .open(filename)
-                .map_err(|e| Error::new(1, format!("Could not open file {filename}: {e}")))?
-        } else {
-            File::create(filename)
-                .map_err(|e| Error::new(1, format!("Could not open file {filename}: {e}")))?
-        });
-
-        files.push((filename, file));
+    if ctx.is_process() {
+        ignore_sigpipe();
     }
 
-    let mut stdin_handle = stdin().lock();
+    let mut failed = false;
+    let mut outputs = vec![Output {
+        name: "stdout",
+        file: None,
+        open: true,
+    }];
 
-    loop {
-        let mut buff = [0u8; BUFFSIZE];
-        let len = stdin_handle
+    let names: Vec<&str> = opts.positionals().collect();
+    for filename in names {
+        match open_output(filename, do_append) {
+            Ok(file) => outputs.push(Output {
+                name: filename,
+                file: Some(file),
+                open: true,
+            }),
+            Err(e) => {
+                ctx.report(&Error::io(filename, e));
+                failed = true;
+            }
+        }
+    }
+
+    let mut buff = [0u8; BUFFSIZE];
+    while outputs.iter().any(|output| output.open) {
+        let len = ctx
+            .stdin
             .read(&mut buff)
-            .map_err(|e| Error::new(1, format!("Could not read from stdin: {e}")))?;
+            .map_err(|e| Error::io("stdin", e))?;
 
         if len == 0 {
             break;
         }
 
-        for (filename, file) in &mut files {
-            file.write_all(&buff[..len])
-                .map_err(|e| Error::new(1, format!("Could not write to file {filename}: {e}")))?;
+        for output in outputs.iter_mut().filter(|output| output.open) {
+            if let Err(e) = write_output(ctx, output, &buff[..len]) {
+                output.open = false;
+                failed |= output_failed(ctx, mode, output.name, e)?;
+            }
         }
     }
 
-    Ok(())
+    if failed {
+        Err(Error::partial())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::io::{self, Write};
+    use std::process;
+
+    use super::util;
+    use crate::context::{Context, Env};
+
+    // Standard output that a reader has gone away from
+    struct ClosedPipe;
+
+    impl Write for ClosedPipe {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn tee_to_closed_pipe(option: Option<&str>) -> (bool, String, String) {
+        let path = env::temp_dir().join(format!("reutils-tee-{}", process::id()));
+        let name = path.to_str().unwrap();
+
+        let env = Env::new();
+        let (mut stdin, mut stdout, mut stderr) = (&b"data\n"[..], ClosedPipe, Vec::new());
+        let mut ctx = Context::new(&mut stdin, &mut stdout, &mut stderr, &env);
+        ctx.set_arg0("tee");
+
+        let mut args = vec!["tee".to_string()];
+        args.extend(option.map(String::from));
+        args.push(name.to_string());
+        let ok = util(&mut ctx, &args).is_ok();
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        (ok, written, String::from_utf8(stderr).unwrap())
+    }
+
+    #[test]
+    fn closed_pipe() {
+        // The file still gets everything either way; only the complaint and status differ
+        let (ok, written, complaint) = tee_to_closed_pipe(None);
+        assert!(!ok);
+        assert_eq!(written, "data\n");
+        assert!(complaint.starts_with("tee: stdout: "));
+        assert_eq!(
+            tee_to_closed_pipe(Some("-p")),
+            (true, "data\n".to_string(), String::new())
+        );
+        assert_eq!(
+            tee_to_closed_pipe(Some("--output-error=exit-nopipe")),
+            (true, "data\n".to_string(), String::new())
+        );
+    }
 }
@@ -3,9 +3,19 @@
  * SPDX-License-Identifier: GPL-2.0-only
  */
 
+use crate::argspec::ArgSpec;
+use crate::context::Context;
 use crate::err::Result;
 
+pub const SPEC: ArgSpec = ArgSpec {
+    about: "Return true value (exit status 0). Any arguments are ignored.",
+    operands: &[],
+    options: &[],
+    exclusive: &[],
+    raw_args: true,
+};
+
 #[allow(clippy::unnecessary_wraps)]
-pub const fn util(_args: &[String]) -> Result {
+pub fn util(_ctx: &mut Context, _args: &[String]) -> Result {
     Ok(())
 }
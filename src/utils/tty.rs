@@ -3,23 +3,37 @@
  * SPDX-License-Identifier: GPL-2.0-only
  */
 
+use getargs::Opt;
+
+use crate::argspec::{ArgParser, ArgSpec, OptSpec};
+use crate::context::Context;
 use crate::err::{Error, Result};
 
+pub const SPEC: ArgSpec = ArgSpec {
+    about: "Print the file name of the terminal connected to standard input.",
+    operands: &[],
+    options: &[OptSpec::short('s', "print nothing, only return an exit status").alias("silent")],
+    exclusive: &[],
+    raw_args: false,
+};
+
+#[cfg(unix)]
+fn not_a_tty() -> Error {
+    Error::new(1, "Could not get TTY name: not a tty".to_string())
+}
+
 #[cfg(unix)]
-fn ttyname() -> Result<String> {
+fn ttyname(ctx: &Context) -> Result<String> {
     use std::ffi::CStr;
     use std::io;
-    use std::os::fd::{AsFd, AsRawFd, BorrowedFd};
 
-    let stdin = io::stdin();
-    let bfd: BorrowedFd<'_> = stdin.as_fd();
-    let fd = bfd.as_raw_fd();
+    // Streams that aren't backed by a descriptor can't possibly be a terminal
+    let fd = ctx.stdin_fd().ok_or_else(not_a_tty)?;
     let name_ptr = unsafe { libc::ttyname(fd) };
 
     if name_ptr.is_null() {
         // Uh oh!
-        let e = io::Error::last_os_error();
-        return Err(Error::new(1, format!("Could not get TTY name: {e}")));
+        return Err(Error::from(io::Error::last_os_error()).context("Could not get TTY name"));
     }
 
     let c_name = unsafe { CStr::from_ptr(name_ptr) };
@@ -31,16 +45,32 @@ fn ttyname() -> Result<String> {
 }
 
 #[cfg(windows)]
-fn ttyname() -> Result<String> {
+fn ttyname(_ctx: &Context) -> Result<String> {
     /* FIXME - MingW does emulate a pty device... but we can't rely on it.
      * Windows does have some PTY stuff in modern versions, but, idk if we can use it?
      * Hard to say.
      * --Elizafox
      */
-    Err(Error::new(255, "Not implemented on Windows".to_string()))
+    Err(Error::new(1, "Not implemented on Windows".to_string()))
 }
 
-pub fn util(_args: &[String]) -> Result {
-    println!("{}", ttyname()?);
+pub fn util(ctx: &mut Context, args: &[String]) -> Result {
+    let mut silent = false;
+
+    let mut opts = ArgParser::new(&SPEC, args);
+    while let Some(opt) = opts.next_opt()? {
+        match opt {
+            Opt::Short('s') => silent = true,
+            _ => unreachable!(),
+        }
+    }
+
+    let name = ttyname(ctx);
+
+    if silent {
+        return name.map(|_| ()).map_err(|_| Error::new_nomsg(1));
+    }
+
+    writeln!(ctx.stdout, "{}", name?)?;
     Ok(())
 }
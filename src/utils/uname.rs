@@ -3,10 +3,27 @@
  * SPDX-License-Identifier: GPL-2.0-only
  */
 
-use getargs::{Opt, Options};
+use getargs::Opt;
 
+use crate::argspec::{ArgParser, ArgSpec, OptSpec};
+use crate::context::Context;
 use crate::err::Result;
 
+pub const SPEC: ArgSpec = ArgSpec {
+    about: "Print system information.",
+    operands: &[],
+    options: &[
+        OptSpec::short('a', "print all information").alias("all"),
+        OptSpec::short('m', "print the machine hardware name").alias("machine"),
+        OptSpec::short('n', "print the network node hostname").alias("nodename"),
+        OptSpec::short('r', "print the operating system release").alias("kernel-release"),
+        OptSpec::short('s', "print the operating system name").alias("kernel-name"),
+        OptSpec::short('v', "print the operating system version").alias("kernel-version"),
+    ],
+    exclusive: &[],
+    raw_args: false,
+};
+
 mod args {
     #[derive(Debug, PartialEq, Eq)]
     pub enum SysName {
@@ -147,17 +164,12 @@ fn uname(uargs: &args::UnameFlags) -> String {
     ret.trim_end().to_string()
 }
 
-#[allow(clippy::unnecessary_wraps)]
-pub fn util(args: &[String]) -> Result {
+pub fn util(ctx: &mut Context, args: &[String]) -> Result {
     let mut uargs = args::UnameFlags::new();
 
-    let mut opts = Options::new(args.iter().skip(1).map(String::as_str));
-    while let Some(opt) = opts.next_opt().expect("argument parsing error") {
+    let mut opts = ArgParser::new(&SPEC, args);
+    while let Some(opt) = opts.next_opt()? {
         match opt {
-            Opt::Short('h') | Opt::Long("help") => {
-                eprintln!("Usage: {} [-amnrsv]", args[0]);
-                return Ok(());
-            }
             Opt::Short('a') => {
                 uargs.machine = args::Machine::ShowMachine;
                 uargs.nodename = args::NodeName::ShowNodeName;
@@ -170,7 +182,7 @@ pub fn util(args: &[String]) -> Result {
             Opt::Short('r') => uargs.release = args::Release::ShowRelease,
             Opt::Short('s') => uargs.sysname = args::SysName::ShowSysName,
             Opt::Short('v') => uargs.version = args::Version::ShowVersion,
-            _ => {}
+            _ => unreachable!(),
         }
     }
 
@@ -183,7 +195,7 @@ pub fn util(args: &[String]) -> Result {
         uargs.sysname = args::SysName::ShowSysName;
     }
 
-    println!("{}", uname(&uargs));
+    writeln!(ctx.stdout, "{}", uname(&uargs))?;
 
     Ok(())
 }
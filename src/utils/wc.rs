@@ -3,15 +3,42 @@
  * SPDX-License-Identifier: GPL-2.0-only
  */
 
-use std::fs::File;
+mod count;
+mod width;
+
+use std::fs::{self, File};
 use std::io;
 use std::io::prelude::*;
+use std::str;
 
-use getargs::{Opt, Options};
+use getargs::Opt;
 
-use crate::bufinput::BufInput;
+use crate::argspec::{ArgParser, ArgSpec, OptSpec};
+use crate::bufinput::{BufInput, Operands};
+use crate::context::Context;
 use crate::err::{Error, Result};
 
+use self::count::Counter;
+
+pub const SPEC: ArgSpec = ArgSpec {
+    about: "Print newline, word, and byte or character counts for each file.",
+    operands: &["[file...]"],
+    options: &[
+        OptSpec::short('c', "print the byte counts").alias("bytes"),
+        OptSpec::short('m', "print the character counts").alias("chars"),
+        OptSpec::short('l', "print the newline counts").alias("lines"),
+        OptSpec::short('w', "print the word counts").alias("words"),
+        OptSpec::short('L', "print the width of the longest line").alias("max-line-length"),
+        OptSpec::long(
+            "files0-from",
+            "read NUL-separated file names from file (- for stdin)",
+        )
+        .value("file"),
+    ],
+    exclusive: &[&['c', 'm']],
+    raw_args: false,
+};
+
 // 4 blocks on an AF disk at a time, or 32 blocks on a traditional disk
 // Good enough for anyone.
 const BUFFSIZE: usize = 16384usize;
@@ -29,6 +56,7 @@ pub struct Flags {
     pub chars_bytes: FlagsUnitType,
     pub words: bool,
     pub lines: bool,
+    pub max_line: bool,
 }
 
 impl Flags {
@@ -38,6 +66,7 @@ impl Flags {
             chars_bytes: FlagsUnitType::NoneType,
             words: false,
             lines: false,
+            max_line: false,
         }
     }
 }
@@ -46,6 +75,7 @@ pub struct Stats {
     pub chars: usize,
     pub words: usize,
     pub lines: usize,
+    pub max_line: usize,
     pub encoding_error: Option<io::Error>,
 }
 
@@ -56,174 +86,127 @@ impl Stats {
             chars: 0usize,
             words: 0usize,
             lines: 0usize,
+            max_line: 0usize,
             encoding_error: None,
         }
     }
 }
 
-// Used to keep track of counting state
-pub struct CountStatsState {
-    pub in_word: bool,
-}
-
-impl CountStatsState {
-    #[must_use]
-    pub const fn new() -> Self {
-        Self { in_word: false }
-    }
-}
-
-// Get the counts stats for a string, returning what a human "expects".
-fn count_stats_str(string: &str, tracking_state: &mut CountStatsState) -> Stats {
-    let mut stats = Stats::new();
-
-    for c in string.chars() {
-        stats.chars += 1;
-
-        if c.is_whitespace() {
-            if c == '\n' {
-                stats.lines += 1;
-            }
-            if tracking_state.in_word {
-                stats.words += 1;
-                tracking_state.in_word = false;
-            }
-        } else {
-            tracking_state.in_word = true;
-        }
-    }
-
-    stats
-}
-
-// Get the counts stats for a bunch of bytes
-fn count_stats_bytes(bytes: &[u8], tracking_state: &mut CountStatsState) -> Stats {
-    let mut stats = Stats::new();
-    stats.chars = bytes.len();
-
-    for b in bytes {
-        // Whitespace
-        if (*b >= b'\x09' && *b <= b'\x0D') || *b == b'\x20' {
-            if *b == b'\n' {
-                stats.lines += 1;
-            }
-            if tracking_state.in_word {
-                stats.words += 1;
-                tracking_state.in_word = false;
-            }
-        } else {
-            tracking_state.in_word = true;
-        }
-    }
-
-    stats
-}
-
 fn read_file(reader: &mut BufInput, flags: &Flags) -> io::Result<Stats> {
-    let mut stats = Stats::new();
+    let mut counter = Counter::new(flags.chars_bytes == FlagsUnitType::Char, flags.words);
+    if flags.max_line {
+        counter = counter.with_widths();
+    }
 
     let mut buffer = [0u8; BUFFSIZE];
-    let mut tracking_state = CountStatsState::new();
     let mut start = 0usize;
-    let mut has_eof = false;
-    while !has_eof || start > 0 {
-        let len = if !has_eof && start < buffer.len() {
-            // Ingest more data
-            let rlen = reader.read(&mut buffer[start..])?;
-            if rlen == 0 {
-                has_eof = true;
-
-                if start > 0 {
-                    // We still have characters in the buffer.
-                    // We should parse them.
-                    start
-                } else {
-                    // If we were still in a word, then count the last one.
-                    if tracking_state.in_word {
-                        stats.words += 1;
-                    }
-
-                    continue;
-                }
-            } else {
-                // Not EOF, combine starting position and length
-                rlen + start
-            }
-        } else {
-            // Process the string, do not ingest more data.
-            start
-        };
-
-        let this_stats = if flags.chars_bytes == FlagsUnitType::Char {
-            // Unicode
-            let str_data;
-            match std::str::from_utf8(&buffer[..len]) {
-                Ok(data) => {
-                    // Valid string, good to go
-                    str_data = data.to_string();
-                    start = 0;
-                }
-                Err(e) => {
-                    // Hmm, seems we have a problem.
-                    let valid_up_to = e.valid_up_to();
-                    str_data = unsafe { std::str::from_utf8_unchecked(&buffer[..valid_up_to]) }
-                        .to_string();
-
-                    if let Some(invalid_bytes) = e.error_len() {
-                        // Skip the bad bits.
-                        stats.encoding_error = Some(io::Error::from(io::ErrorKind::InvalidData));
-                        let skip = invalid_bytes + valid_up_to;
-                        buffer.copy_within(skip..len, 0);
-                        start = len - skip;
-                    } else {
-                        // This is part of another char, just put it at the beginning.
-                        buffer.copy_within(valid_up_to..len, 0);
-                        start = len - valid_up_to;
-                    }
-                }
-            }
-
-            count_stats_str(&str_data, &mut tracking_state)
-        } else {
-            count_stats_bytes(&buffer[..len], &mut tracking_state)
+    loop {
+        let len = match reader.read(&mut buffer[start..]) {
+            Ok(0) => break,
+            Ok(rlen) => start + rlen,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
         };
 
-        stats.lines += this_stats.lines;
-        stats.words += this_stats.words;
-        stats.chars += this_stats.chars;
+        // Keep anything the counter couldn't use yet (part of a character) for next time
+        let used = counter.update(&buffer[..len]);
+        buffer.copy_within(used..len, 0);
+        start = len - used;
     }
 
-    Ok(stats)
+    let counts = counter.finish(&buffer[..start]);
+    Ok(Stats {
+        chars: counts.chars,
+        words: counts.words,
+        lines: counts.lines,
+        max_line: counts.max_line,
+        encoding_error: counts.encoding_error.then(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid or incomplete multibyte or wide character",
+            )
+        }),
+    })
 }
 
-fn print_stats(flags: &Flags, stats: &Stats, filename: &str) {
-    if flags.lines {
-        print!(" {}", stats.lines);
+/* Columns line up the way GNU wc does it: wide enough for the combined size of all the regular
+ * files, since no count can be bigger than that, or seven digits if there's something we can't
+ * know the size of in advance (stdin, pipes, devices). A single count for a single file isn't
+ * padded at all.
+ *
+ * POSIX wants "%d %d %d %s\n", which is just a width of one, so that's what POSIXLY_CORRECT gets.
+ * --Elizafox
+ */
+fn column_width(names: &[&str], flags: &Flags) -> usize {
+    let columns = [
+        flags.lines,
+        flags.words,
+        flags.chars_bytes != FlagsUnitType::NoneType,
+        flags.max_line,
+    ];
+    if names.len() == 1 && columns.iter().filter(|wanted| **wanted).count() == 1 {
+        return 1;
     }
 
-    if flags.words {
-        print!(" {}", stats.words);
+    let mut minimum = 1usize;
+    let mut size = 0u64;
+    for name in names {
+        if *name == "-" {
+            minimum = 7;
+            continue;
+        }
+
+        match fs::metadata(name) {
+            Ok(metadata) if metadata.is_file() => size += metadata.len(),
+            Ok(_) => minimum = 7,
+            // This gets reported when we get to it
+            Err(_) => {}
+        }
     }
 
-    if flags.chars_bytes != FlagsUnitType::NoneType {
-        print!(" {}", stats.chars);
+    size.to_string().len().max(minimum)
+}
+
+fn print_stats(
+    out: &mut dyn Write,
+    flags: &Flags,
+    width: usize,
+    stats: &Stats,
+    filename: &str,
+) -> io::Result<()> {
+    let columns = [
+        (flags.lines, stats.lines),
+        (flags.words, stats.words),
+        (flags.chars_bytes != FlagsUnitType::NoneType, stats.chars),
+        (flags.max_line, stats.max_line),
+    ];
+
+    let mut separator = "";
+    for (_, count) in columns.iter().filter(|(wanted, _)| *wanted) {
+        write!(out, "{separator}{count:>width$}")?;
+        separator = " ";
     }
 
     if filename.is_empty() {
-        println!();
+        writeln!(out)
     } else {
-        println!(" {filename}");
+        writeln!(out, " {filename}")
     }
 }
 
-// XXX - bool param for signalling encoding errors is bogus
 fn handle_file(reader: &mut BufInput, flags: &Flags) -> io::Result<Stats> {
     let mut stats: Stats;
     #[allow(clippy::cast_possible_truncation)]
-    if flags.chars_bytes == FlagsUnitType::Byte && !flags.lines && !flags.words && reader.is_file()
+    if flags.chars_bytes == FlagsUnitType::Byte
+        && !flags.lines
+        && !flags.words
+        && !flags.max_line
+        && reader.is_file()
     {
         // If we just have -c, and it's a normal reader, we can just stat the reader and go home.
-        let BufInput::File(f) = reader else { unreachable!() };
+        let BufInput::File(f) = reader else {
+            unreachable!()
+        };
         let metadata = f.get_ref().metadata()?;
         stats = Stats::new();
         stats.chars = metadata.len() as usize;
@@ -234,16 +217,49 @@ fn handle_file(reader: &mut BufInput, flags: &Flags) -> io::Result<Stats> {
     Ok(stats)
 }
 
-fn usage(arg0: &str) {
-    eprintln!("Usage: {arg0} [-c|-m] [-lw] [file...]");
+// File names for --files0-from, separated by NULs; the flag is set if any were bad
+fn read_names(ctx: &mut Context, from: &str) -> Result<(Vec<String>, bool)> {
+    let mut data = Vec::new();
+    if from == "-" {
+        ctx.stdin.read_to_end(&mut data)
+    } else {
+        File::open(from).and_then(|mut f| f.read_to_end(&mut data))
+    }
+    .map_err(|e| Error::io(from, e).context("cannot open file names list"))?;
+
+    if data.last() == Some(&0) {
+        data.pop();
+    }
+    if data.is_empty() {
+        return Ok((Vec::new(), false));
+    }
+
+    let mut names = Vec::new();
+    let mut bad = false;
+    for (n, name) in data.split(|b| *b == 0).enumerate() {
+        let message = match str::from_utf8(name) {
+            Ok("") => "invalid zero-length file name",
+            Ok("-") if from == "-" => "can't read file names and data both from stdin",
+            Ok(name) => {
+                names.push(name.to_string());
+                continue;
+            }
+            Err(_) => "file name isn't valid UTF-8",
+        };
+        ctx.report(&Error::new(1, format!("{from}:{}: {message}", n + 1)));
+        bad = true;
+    }
+
+    Ok((names, bad))
 }
 
-pub fn util(args: &[String]) -> Result {
+pub fn util(ctx: &mut Context, args: &[String]) -> Result {
     let mut do_default = true;
     let mut flags = Flags::new();
+    let mut files0_from = None;
 
-    let mut opts = Options::new(args.iter().skip(1).map(String::as_str));
-    while let Some(opt) = opts.next_opt().expect("argument parsing error") {
+    let mut opts = ArgParser::new(&SPEC, args);
+    while let Some(opt) = opts.next_opt()? {
         match opt {
             Opt::Short('c') => {
                 do_default = false;
@@ -261,11 +277,12 @@ pub fn util(args: &[String]) -> Result {
                 do_default = false;
                 flags.words = true;
             }
-            Opt::Short('h') | Opt::Long("help") => {
-                usage(&args[0]);
-                return Ok(());
+            Opt::Short('L') => {
+                do_default = false;
+                flags.max_line = true;
             }
-            _ => {}
+            Opt::Long("files0-from") => files0_from = Some(opts.value()),
+            _ => unreachable!(),
         }
     }
 
@@ -276,56 +293,72 @@ pub fn util(args: &[String]) -> Result {
         flags.words = true;
     }
 
-    let mut files: Vec<(&str, BufInput)> = Vec::new();
-
-    for filename in opts.positionals() {
-        if filename == "-" {
-            files.push(("stdin", BufInput::Standard(io::stdin().lock())));
-        } else {
-            let file = File::open(filename);
-            match file {
-                Ok(file) => {
-                    files.push((filename, BufInput::File(io::BufReader::new(file))));
-                }
-                Err(e) => {
-                    return Err(Error::new(
-                        1,
-                        format!("Could not open file: {filename}: {e}"),
-                    ));
-                }
-            }
+    let posix = ctx.env.contains_key("POSIXLY_CORRECT");
+    let mut names: Vec<&str> = opts.positionals().collect();
+    let mut name_stdin = true;
+    let mut failed = false;
+
+    let from_file;
+    if let Some(from) = files0_from {
+        if let Some(name) = names.first() {
+            return Err(opts.usage_error(&format!(
+                "extra operand '{name}' (file operands can't be combined with --files0-from)"
+            )));
         }
+
+        (from_file, failed) = read_names(ctx, from)?;
+        names = from_file.iter().map(String::as_str).collect();
+    } else if names.is_empty() {
+        // If ain't nobody got me, stdin got me. POSIX says not to name it in that case.
+        names.push("-");
+        name_stdin = false;
     }
 
-    if files.is_empty() {
-        // If ain't nobody got me, stdin got me.
-        files.push(("stdin", BufInput::Standard(io::stdin().lock())));
+    let width = if posix {
+        1
+    } else {
+        column_width(&names, &flags)
+    };
+
+    // An empty --files0-from list means no files at all, not stdin
+    if names.is_empty() {
+        return if failed {
+            Err(Error::partial())
+        } else {
+            Ok(())
+        };
     }
 
-    let file_count = files.len();
+    let mut total = Stats::new();
+    let mut operands = Operands::new(names.iter().copied());
+    operands.for_each_parallel(
+        ctx,
+        |input| handle_file(input, &flags),
+        |ctx, label, mut stats| {
+            if let Some(encoding_error) = stats.encoding_error.take() {
+                ctx.report(&Error::io(label, encoding_error));
+                failed = true;
+            }
 
-    let mut stats = Stats::new();
-    for (filename, ref mut file) in &mut files {
-        let result = handle_file(file, &flags);
-        match result {
-            Ok(stats_result) => {
-                if let Some(ref encoding_error) = stats.encoding_error {
-                    eprintln!("{}: {filename}: {encoding_error}", args[0]);
-                }
+            // Diagnostics need a name even when the output doesn't have one
+            let label = if name_stdin { label } else { "" };
+            print_stats(ctx.stdout, &flags, width, &stats, label)?;
 
-                print_stats(&flags, &stats_result, filename);
+            total.lines += stats.lines;
+            total.words += stats.words;
+            total.chars += stats.chars;
+            total.max_line = total.max_line.max(stats.max_line);
+            Ok(())
+        },
+    )?;
 
-                stats.lines += stats_result.lines;
-                stats.words += stats_result.words;
-                stats.chars += stats_result.chars;
-            }
-            Err(e) => eprintln!("{}: Error reading file {filename}: {e}", args[0]),
-        }
+    if names.len() > 1 {
+        print_stats(ctx.stdout, &flags, width, &total, "total")?;
     }
 
-    if file_count > 1usize {
-        print_stats(&flags, &stats, "total");
+    if failed {
+        Err(Error::partial())
+    } else {
+        operands.finish()
     }
-
-    Ok(())
 }
@@ -0,0 +1,432 @@
+/* utils/wc/count.rs - counting engine for wc
+ * Copyright (C) 2023 Elizabeth Myers. All rights reserved.
+ * SPDX-License-Identifier: GPL-2.0-only
+ */
+
+/* wc spends all its time in here, so it doesn't look at bytes one at a time if it can help it.
+ *
+ * Everything works on 8 bytes at once, packed into a u64 (SWAR, "SIMD within a register"), with a
+ * flag for each byte kept in that byte's top bit. That gets newlines and word starts with a few
+ * ALU ops and a popcount per 8 bytes, and the compiler will happily widen the loops further on
+ * machines with real vector units. It's all portable, with no dependencies.
+ *
+ * Words are counted by where they start: a non-space byte whose predecessor was a space (or the
+ * start of the file). The predecessor of the first byte in each block comes from the last block,
+ * so words that span blocks or reads come out right.
+ *
+ * With -m, we count characters instead of bytes, without decoding or copying anything: in valid
+ * UTF-8, every byte that isn't a continuation byte (10xxxxxx) starts a character. Invalid
+ * sequences aren't characters, and don't affect words either; we just note that we saw them. Runs
+ * of ASCII still go through the fast path for words; only non-ASCII characters get decoded, since
+ * some of them are spaces.
+ *
+ * -L (the longest line, in columns) has to look at every byte or character no matter what, since
+ * tabs and wide characters make it impossible to tell from the length alone. We only do that work
+ * when it's asked for.
+ * --Elizafox
+ */
+
+use std::str;
+
+use super::width::width;
+
+const ONES: u64 = 0x0101_0101_0101_0101;
+const HIGH: u64 = 0x8080_8080_8080_8080;
+const LOW7: u64 = 0x7f7f_7f7f_7f7f_7f7f;
+
+#[derive(Default)]
+pub struct Counts {
+    pub chars: usize,
+    pub words: usize,
+    pub lines: usize,
+    pub max_line: usize,
+    pub encoding_error: bool,
+}
+
+const fn splat(byte: u8) -> u64 {
+    byte as u64 * ONES
+}
+
+// Top bit set in each byte that is zero; exact, unlike the usual haszero() trick
+const fn zero_bytes(x: u64) -> u64 {
+    !(((x & LOW7) + LOW7) | x | LOW7)
+}
+
+const fn eq_bytes(x: u64, byte: u8) -> u64 {
+    zero_bytes(x ^ splat(byte))
+}
+
+// Top bit set in each byte less than n, for n <= 0x80; no byte can borrow from its neighbour
+const fn lt_bytes(x: u64, n: u8) -> u64 {
+    !((x | HIGH) - splat(n)) & !x & HIGH
+}
+
+// Tab, newline, vertical tab, form feed, carriage return, and space
+const fn space_bytes(x: u64) -> u64 {
+    eq_bytes(x, b' ') | (lt_bytes(x, b'\r' + 1) & !lt_bytes(x, b'\t'))
+}
+
+const fn is_space(byte: u8) -> bool {
+    matches!(byte, b'\t'..=b'\r' | b' ')
+}
+
+fn load(block: &[u8]) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(block);
+    u64::from_le_bytes(bytes)
+}
+
+#[allow(clippy::naive_bytecount)] // Only the last few bytes; the bytecount crate isn't worth it
+pub fn count_lines(data: &[u8]) -> usize {
+    let blocks = data.chunks_exact(8);
+    let tail = blocks.remainder();
+    let lines: u32 = blocks.map(|b| eq_bytes(load(b), b'\n').count_ones()).sum();
+    lines as usize + tail.iter().filter(|b| **b == b'\n').count()
+}
+
+// Characters in valid UTF-8
+fn count_chars(data: &[u8]) -> usize {
+    let blocks = data.chunks_exact(8);
+    let tail = blocks.remainder();
+    let continuations: u32 = blocks
+        .map(|b| {
+            let x = load(b);
+            (x & !(x << 1) & HIGH).count_ones()
+        })
+        .sum();
+    let continuations = continuations as usize + tail.iter().filter(|b| **b & 0xc0 == 0x80).count();
+    data.len() - continuations
+}
+
+// How much of data is ASCII before the first byte that isn't
+fn ascii_len(data: &[u8]) -> usize {
+    data.chunks(8)
+        .position(|b| !b.is_ascii())
+        .map_or(data.len(), |i| {
+            let start = i * 8;
+            start + data[start..].iter().take_while(|b| b.is_ascii()).count()
+        })
+}
+
+#[allow(clippy::struct_excessive_bools)] // What to count, and whether we're in a word
+pub struct Counter {
+    chars: bool,
+    words: bool,
+    widths: bool,
+    in_word: bool,
+    column: usize,
+    counts: Counts,
+}
+
+impl Counter {
+    // chars is whether to count characters (-m) rather than bytes; words is whether to bother
+    #[must_use]
+    pub fn new(chars: bool, words: bool) -> Self {
+        Self {
+            chars,
+            words,
+            widths: false,
+            in_word: false,
+            column: 0,
+            counts: Counts::default(),
+        }
+    }
+
+    // Keep track of the longest line too (-L)
+    #[must_use]
+    pub const fn with_widths(mut self) -> Self {
+        self.widths = true;
+        self
+    }
+
+    // Move along the line for a character that isn't printable; true if it was one of those
+    fn control(&mut self, c: char) -> bool {
+        match c {
+            '\n' | '\r' | '\x0c' => {
+                self.counts.max_line = self.counts.max_line.max(self.column);
+                self.column = 0;
+            }
+            '\t' => self.column += 8 - self.column % 8,
+            _ if c.is_control() => {}
+            _ => return false,
+        }
+        true
+    }
+
+    // Without -m, every byte that isn't an ASCII control character is a column
+    fn measure_bytes(&mut self, data: &[u8]) {
+        for &byte in data {
+            if byte >= 0x80 || !self.control(char::from(byte)) {
+                self.column += 1;
+            }
+        }
+    }
+
+    fn measure_text(&mut self, text: &str) {
+        for c in text.chars() {
+            if !self.control(c) {
+                self.column += width(c);
+            }
+        }
+    }
+
+    fn count_words(&mut self, data: &[u8]) {
+        let blocks = data.chunks_exact(8);
+        let tail = blocks.remainder();
+
+        for block in blocks {
+            let words = !space_bytes(load(block)) & HIGH;
+            let before = (words << 8) | (u64::from(self.in_word) << 7);
+            self.counts.words += (words & !before).count_ones() as usize;
+            self.in_word = words >> 63 != 0;
+        }
+
+        for &byte in tail {
+            let in_word = !is_space(byte);
+            if in_word && !self.in_word {
+                self.counts.words += 1;
+            }
+            self.in_word = in_word;
+        }
+    }
+
+    // Words in valid UTF-8, where anything Unicode calls a space separates words
+    fn count_words_utf8(&mut self, text: &str) {
+        let mut rest = text;
+        while !rest.is_empty() {
+            let ascii = ascii_len(rest.as_bytes());
+            self.count_words(&rest.as_bytes()[..ascii]);
+            rest = &rest[ascii..];
+
+            let decoded = rest.find(|c: char| c.is_ascii()).unwrap_or(rest.len());
+            for c in rest[..decoded].chars() {
+                let in_word = !c.is_whitespace();
+                if in_word && !self.in_word {
+                    self.counts.words += 1;
+                }
+                self.in_word = in_word;
+            }
+            rest = &rest[decoded..];
+        }
+    }
+
+    fn count_valid(&mut self, text: &str) {
+        let data = text.as_bytes();
+        self.counts.lines += count_lines(data);
+        self.counts.chars += count_chars(data);
+        if self.words {
+            self.count_words_utf8(text);
+        }
+        if self.widths {
+            self.measure_text(text);
+        }
+    }
+
+    /* Count a block of data, returning how much of it was used. With -m, a character split across
+     * the end of the block is left for next time; the caller should hand it back with the next
+     * block (or to finish() if there isn't one).
+     */
+    pub fn update(&mut self, data: &[u8]) -> usize {
+        if !self.chars {
+            self.counts.lines += count_lines(data);
+            self.counts.chars += data.len();
+            if self.words {
+                self.count_words(data);
+            }
+            if self.widths {
+                self.measure_bytes(data);
+            }
+            return data.len();
+        }
+
+        let mut rest = data;
+        loop {
+            match str::from_utf8(rest) {
+                Ok(text) => {
+                    self.count_valid(text);
+                    return data.len();
+                }
+                Err(e) => {
+                    let (valid, invalid) = rest.split_at(e.valid_up_to());
+                    // from_utf8 just said this much was fine
+                    self.count_valid(unsafe { str::from_utf8_unchecked(valid) });
+
+                    let Some(len) = e.error_len() else {
+                        return data.len() - invalid.len();
+                    };
+                    self.counts.encoding_error = true;
+                    rest = &invalid[len..];
+                }
+            }
+        }
+    }
+
+    // Anything left over from update() is a truncated character
+    #[must_use]
+    pub fn finish(mut self, leftover: &[u8]) -> Counts {
+        if !leftover.is_empty() {
+            self.counts.encoding_error = true;
+        }
+        self.counts.max_line = self.counts.max_line.max(self.column);
+        self.counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::{Counter, Counts};
+
+    // The scalar counting wc used to do, byte by byte, to check against and race
+    fn reference(data: &[u8], chars: bool) -> (usize, usize, usize) {
+        let (mut lines, mut words, mut count) = (0, 0, 0);
+        let mut in_word = false;
+        let mut step = |is_space: bool, is_newline: bool| {
+            count += 1;
+            if is_newline {
+                lines += 1;
+            }
+            if is_space {
+                in_word = false;
+            } else if !in_word {
+                in_word = true;
+                words += 1;
+            }
+        };
+
+        if chars {
+            for c in String::from_utf8_lossy(data).chars() {
+                if c != char::REPLACEMENT_CHARACTER {
+                    step(c.is_whitespace(), c == '\n');
+                }
+            }
+        } else {
+            for &b in data {
+                step((b'\t'..=b'\r').contains(&b) || b == b' ', b == b'\n');
+            }
+        }
+
+        (lines, words, count)
+    }
+
+    // Feed data through in awkwardly sized pieces, the way reads might come in
+    fn count_pieces(data: &[u8], chars: bool, piece: usize) -> Counts {
+        let mut counter = Counter::new(chars, true);
+        let mut pending = Vec::new();
+        for block in data.chunks(piece) {
+            pending.extend_from_slice(block);
+            let used = counter.update(&pending);
+            pending.drain(..used);
+        }
+        counter.finish(&pending)
+    }
+
+    // Deterministic junk, heavy on spaces, newlines, and multibyte characters
+    fn sample(len: usize) -> Vec<u8> {
+        let pieces: [&[u8]; 10] = [
+            b"word",
+            b" ",
+            b"\n",
+            b"\t\t",
+            b"caf\xc3\xa9",
+            b"\xe2\x80\x83",
+            b"\xf0\x9f\xa6\x8a",
+            b"\xff",
+            b"\r\n",
+            b"\xc2\xa0x",
+        ];
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut data = Vec::with_capacity(len);
+        while data.len() < len {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            data.extend_from_slice(pieces[(state % 10) as usize]);
+        }
+        data
+    }
+
+    #[test]
+    fn matches_reference() {
+        let data = sample(100_000);
+        for chars in [false, true] {
+            let (lines, words, count) = reference(&data, chars);
+            for piece in [1, 3, 7, 8, 9, 64, 4096, data.len()] {
+                let counts = count_pieces(&data, chars, piece);
+                assert_eq!(
+                    (counts.lines, counts.words, counts.chars),
+                    (lines, words, count),
+                    "chars={chars} piece={piece}"
+                );
+                assert_eq!(counts.encoding_error, chars);
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_character() {
+        let counts = count_pieces(b"ab\xe2\x80", true, 3);
+        assert_eq!((counts.chars, counts.words), (2, 1));
+        assert!(counts.encoding_error);
+    }
+
+    #[test]
+    fn max_line() {
+        let data = "ab\tc\n\u{4e2d}\u{6587}e\u{301}\r\nxyz".as_bytes();
+        for piece in [1, 2, 7, data.len()] {
+            let mut counter = Counter::new(true, false).with_widths();
+            let mut pending = Vec::new();
+            for block in data.chunks(piece) {
+                pending.extend_from_slice(block);
+                let used = counter.update(&pending);
+                pending.drain(..used);
+            }
+            assert_eq!(counter.finish(&pending).max_line, 9, "piece {piece}");
+        }
+
+        let mut counter = Counter::new(false, false).with_widths();
+        counter.update(b"\xe4\xb8\xad\tx\n");
+        assert_eq!(counter.finish(&[]).max_line, 9);
+    }
+
+    // Mostly ASCII text, with the odd multibyte character, like most real files
+    fn text(len: usize) -> Vec<u8> {
+        let line = "The quick brown fox jumps over the lazy dog, caf\u{e9} \u{1f98a}.\n";
+        line.repeat(len / line.len() + 1).into_bytes()
+    }
+
+    fn race(name: &str, data: &[u8], chars: bool, words: bool) {
+        let start = Instant::now();
+        let old = reference(data, chars);
+        let old_time = start.elapsed();
+
+        let start = Instant::now();
+        let mut counter = Counter::new(chars, words);
+        for block in data.chunks(16384) {
+            counter.update(block);
+        }
+        let new = counter.finish(&[]);
+        let new_time = start.elapsed();
+
+        assert_eq!(old.0, new.lines);
+        #[allow(clippy::cast_precision_loss)]
+        let mb = data.len() as f64 / f64::from(1 << 20);
+        println!(
+            "{name}: reference {:.0} MiB/s, engine {:.0} MiB/s",
+            mb / old_time.as_secs_f64(),
+            mb / new_time.as_secs_f64()
+        );
+    }
+
+    // Run with: cargo test --release -- --ignored --nocapture benchmark
+    #[test]
+    #[ignore = "benchmark, not a test"]
+    fn benchmark() {
+        let data = text(256 << 20);
+        race("wc -l", &data, false, false);
+        race("wc", &data, false, true);
+        race("wc -m", &data, true, false);
+        race("wc -mw", &data, true, true);
+    }
+}
@@ -3,6 +3,8 @@
  * SPDX-License-Identifier: GPL-2.0-only
  */
 
+use std::io::{self, Write};
+
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 const GIT_REVISION: &str = env!("VERGEN_GIT_DESCRIBE");
 const BUILD_TIMESTAMP: &str = env!("VERGEN_BUILD_TIMESTAMP");
@@ -20,19 +22,28 @@ const OS_VERSION: &str = env!("VERGEN_SYSINFO_OS_VERSION");
 
 const AUTHORS: &str = env!("REUTILS_PKG_AUTHORS");
 
-pub fn about(verbose: bool) {
-    eprintln!("reutils v{VERSION} (git: {GIT_REVISION})");
+pub fn about(out: &mut dyn Write, verbose: bool) -> io::Result<()> {
+    writeln!(out, "reutils v{VERSION} (git: {GIT_REVISION})")?;
     if verbose {
-        eprintln!("Build OS: {OS_VERSION} ({OS_NAME}) ({BUILD_USER}@{BUILD_HOST})");
-        eprintln!("Build timestamp: {BUILD_TIMESTAMP}");
-        eprintln!("rustc version: {RUSTC_SEMVER} ({RUSTC_CHANNEL}), LLVM {LLVM_VERSION}");
-        eprintln!("rustc host: {RUSTC_HOST_TRIPLE}");
+        writeln!(
+            out,
+            "Build OS: {OS_VERSION} ({OS_NAME}) ({BUILD_USER}@{BUILD_HOST})"
+        )?;
+        writeln!(out, "Build timestamp: {BUILD_TIMESTAMP}")?;
+        writeln!(
+            out,
+            "rustc version: {RUSTC_SEMVER} ({RUSTC_CHANNEL}), LLVM {LLVM_VERSION}"
+        )?;
+        writeln!(out, "rustc host: {RUSTC_HOST_TRIPLE}")?;
         if CARGO_HOST_TRIPLE != RUSTC_HOST_TRIPLE {
-            eprintln!("Cargo host: {CARGO_HOST_TRIPLE}");
+            writeln!(out, "Cargo host: {CARGO_HOST_TRIPLE}")?;
         }
     }
-    eprintln!("Copyright (C) 2023 {AUTHORS}");
-    eprintln!("This program is free software; you may redistribute it under the terms of");
-    eprintln!("the GNU General Public License version 2 ONLY.");
-    eprintln!("This program has absolutely no warranty.");
+    writeln!(out, "Copyright (C) 2023 {AUTHORS}")?;
+    writeln!(
+        out,
+        "This program is free software; you may redistribute it under the terms of"
+    )?;
+    writeln!(out, "the GNU General Public License version 2 ONLY.")?;
+    writeln!(out, "This program has absolutely no warranty.")
 }
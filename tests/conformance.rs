@@ -0,0 +1,338 @@
+/* tests/conformance.rs - golden-file conformance tests for reutils
+ * Copyright (C) 2023 Elizabeth Myers. All rights reserved.
+ * SPDX-License-Identifier: GPL-2.0-only
+ */
+
+/* Every utility in DISPATCH_TABLE must have a directory under tests/fixtures named after it. Each
+ * subdirectory of that is one case, made up of the following files (all optional):
+ *
+ *   args       arguments, one per line
+ *   stdin      fed to the utility as standard input
+ *   stdout     expected standard output (default empty)
+ *   stderr     expected standard error (default empty)
+ *   status     expected exit status (default 0)
+ *   env        environment, KEY=VALUE one per line; the environment is otherwise empty
+ *   files/     copied into the temporary directory the case is run in
+ *   symlinks   symlinks to create in that directory, "link -> target" one per line
+ *
+ * {cwd} in args, env, stdout, and stderr stands for the path of the temporary directory.
+ *
+ * Run with REUTILS_BLESS=1 to overwrite stdout, stderr, and status with whatever the utilities
+ * actually do. Check the diff before committing!
+ *
+ * The working directory is process-wide, so the cases are all run one at a time from a single
+ * test. Don't split this up.
+ * --Elizafox
+ */
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use reutils::{Env, DISPATCH_TABLE};
+
+const CWD_MARKER: &str = "{cwd}";
+
+struct Case {
+    name: String,
+    dir: PathBuf,
+    args: Vec<String>,
+    stdin: Vec<u8>,
+    env: Vec<(String, String)>,
+    symlinks: Vec<(String, String)>,
+}
+
+struct Outcome {
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    status: i32,
+}
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+}
+
+fn read_optional(path: &Path) -> Vec<u8> {
+    match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => panic!("Could not read {}: {e}", path.display()),
+    }
+}
+
+fn read_lines(path: &Path) -> Vec<String> {
+    String::from_utf8(read_optional(path))
+        .unwrap_or_else(|_| panic!("{} is not valid UTF-8", path.display()))
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+impl Case {
+    fn load(util: &str, dir: PathBuf) -> Self {
+        let name = format!("{util}/{}", dir.file_name().unwrap().to_string_lossy());
+
+        let env = read_lines(&dir.join("env"))
+            .into_iter()
+            .map(|line| match line.split_once('=') {
+                Some((k, v)) => (k.to_string(), v.to_string()),
+                None => panic!("{name}: bad env line: {line}"),
+            })
+            .collect();
+
+        let symlinks = read_lines(&dir.join("symlinks"))
+            .into_iter()
+            .map(|line| match line.split_once(" -> ") {
+                Some((link, target)) => (link.to_string(), target.to_string()),
+                None => panic!("{name}: bad symlinks line: {line}"),
+            })
+            .collect();
+
+        Self {
+            args: read_lines(&dir.join("args")),
+            stdin: read_optional(&dir.join("stdin")),
+            env,
+            symlinks,
+            name,
+            dir,
+        }
+    }
+
+    // Set up a fresh working directory for the case
+    fn prepare(&self, cwd: &Path) {
+        fs::create_dir_all(cwd).unwrap();
+
+        let files = self.dir.join("files");
+        if files.is_dir() {
+            copy_dir(&files, cwd);
+        }
+
+        for (link, target) in &self.symlinks {
+            make_symlink(target, &cwd.join(link));
+        }
+    }
+
+    fn run(&self, util: &str, cwd: &Path) -> Outcome {
+        let cwd_str = cwd.to_str().unwrap();
+        let args: Vec<String> = self
+            .args
+            .iter()
+            .map(|a| a.replace(CWD_MARKER, cwd_str))
+            .collect();
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let env: Env = self
+            .env
+            .iter()
+            .map(|(k, v)| (k.clone(), v.replace(CWD_MARKER, cwd_str)))
+            .collect();
+
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        let status = reutils::run(
+            util,
+            &args,
+            &mut &self.stdin[..],
+            &mut stdout,
+            &mut stderr,
+            &env,
+        );
+
+        Outcome {
+            stdout: unsubstitute(&stdout, cwd_str),
+            stderr: unsubstitute(&stderr, cwd_str),
+            status,
+        }
+    }
+
+    // Compare against the expected results, returning a report of any differences
+    fn check(&self, outcome: &Outcome) -> Option<String> {
+        let mut report = String::new();
+
+        let expected_status = String::from_utf8(read_optional(&self.dir.join("status")))
+            .unwrap()
+            .trim()
+            .parse::<i32>()
+            .unwrap_or(0);
+        if outcome.status != expected_status {
+            let _ = writeln!(
+                report,
+                "exit status: expected {expected_status}, got {}",
+                outcome.status
+            );
+        }
+
+        for (stream, actual) in [("stdout", &outcome.stdout), ("stderr", &outcome.stderr)] {
+            let expected = read_optional(&self.dir.join(stream));
+            if expected != *actual {
+                let _ = writeln!(report, "{stream} differs:");
+                report.push_str(&diff(
+                    &String::from_utf8_lossy(&expected),
+                    &String::from_utf8_lossy(actual),
+                ));
+            }
+        }
+
+        if report.is_empty() {
+            None
+        } else {
+            Some(format!("--- {} ---\n{report}", self.name))
+        }
+    }
+
+    fn bless(&self, outcome: &Outcome) {
+        for (stream, actual) in [("stdout", &outcome.stdout), ("stderr", &outcome.stderr)] {
+            let path = self.dir.join(stream);
+            if actual.is_empty() {
+                let _ = fs::remove_file(path);
+            } else {
+                fs::write(path, actual).unwrap();
+            }
+        }
+
+        let path = self.dir.join("status");
+        if outcome.status == 0 {
+            let _ = fs::remove_file(path);
+        } else {
+            fs::write(path, format!("{}\n", outcome.status)).unwrap();
+        }
+    }
+}
+
+// Put the {cwd} marker back in output, so it doesn't depend on where the case ran
+fn unsubstitute(output: &[u8], cwd: &str) -> Vec<u8> {
+    match String::from_utf8(output.to_vec()) {
+        Ok(s) => s.replace(cwd, CWD_MARKER).into_bytes(),
+        Err(_) => output.to_vec(),
+    }
+}
+
+fn copy_dir(from: &Path, to: &Path) {
+    for entry in fs::read_dir(from).unwrap() {
+        let entry = entry.unwrap();
+        let dest = to.join(entry.file_name());
+        if entry.file_type().unwrap().is_dir() {
+            fs::create_dir_all(&dest).unwrap();
+            copy_dir(&entry.path(), &dest);
+        } else {
+            fs::copy(entry.path(), dest).unwrap();
+        }
+    }
+}
+
+#[cfg(unix)]
+fn make_symlink(target: &str, link: &Path) {
+    std::os::unix::fs::symlink(target, link).unwrap();
+}
+
+#[cfg(windows)]
+fn make_symlink(target: &str, link: &Path) {
+    std::os::windows::fs::symlink_file(target, link).unwrap();
+}
+
+// A line-based diff of expected against actual; good enough for small outputs
+fn diff(expected: &str, actual: &str) -> String {
+    let a: Vec<&str> = expected.split_inclusive('\n').collect();
+    let b: Vec<&str> = actual.split_inclusive('\n').collect();
+
+    // Longest common subsequence table, built from the end
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut ret = String::new();
+    let mut line = |prefix: char, text: &str| {
+        let _ = writeln!(ret, "{prefix}{text:?}");
+    };
+
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            line(' ', a[i]);
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            line('-', a[i]);
+            i += 1;
+        } else {
+            line('+', b[j]);
+            j += 1;
+        }
+    }
+
+    ret
+}
+
+fn cases_for(util: &str) -> Vec<Case> {
+    let dir = fixtures_dir().join(util);
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Vec::new();
+    };
+
+    let mut dirs: Vec<PathBuf> = entries
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.is_dir())
+        .collect();
+    dirs.sort();
+
+    dirs.into_iter().map(|d| Case::load(util, d)).collect()
+}
+
+#[test]
+fn conformance() {
+    let bless = env::var_os("REUTILS_BLESS").is_some();
+    let scratch = env::temp_dir().join(format!("reutils-conformance-{}", process::id()));
+    let original_cwd = env::current_dir().unwrap();
+
+    let mut failures = Vec::<String>::new();
+    let mut count = 0usize;
+
+    for util in DISPATCH_TABLE.keys() {
+        let cases = cases_for(util);
+        if cases.is_empty() {
+            failures.push(format!(
+                "--- {util} ---\nno fixtures in tests/fixtures/{util}\n"
+            ));
+            continue;
+        }
+
+        for case in cases {
+            count += 1;
+
+            // Canonicalise so utilities that resolve paths agree with {cwd}
+            let cwd = scratch.join(format!("case{count}"));
+            case.prepare(&cwd);
+            let cwd = fs::canonicalize(&cwd).unwrap();
+
+            env::set_current_dir(&cwd).unwrap();
+            let outcome = case.run(util, &cwd);
+            env::set_current_dir(&original_cwd).unwrap();
+
+            if bless {
+                case.bless(&outcome);
+            } else if let Some(report) = case.check(&outcome) {
+                failures.push(report);
+            }
+        }
+    }
+
+    let _ = fs::remove_dir_all(&scratch);
+
+    assert!(
+        failures.is_empty(),
+        "{} of {count} conformance cases failed:\n\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+}
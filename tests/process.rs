@@ -0,0 +1,335 @@
+/* tests/process.rs - tests running the reutils binary as its own process
+ * Copyright (C) 2023 Elizabeth Myers. All rights reserved.
+ * SPDX-License-Identifier: GPL-2.0-only
+ */
+
+/* Some things only happen when a utility has the process to itself: cat handing the copy to the
+ * kernel needs a real stdout file descriptor, and --install copies the running binary. reutils::run()
+ * can't get at those, so these run the built binary instead, under the name of the utility being
+ * tested. This is also the place for anything that needs other programs, like the shells that check
+ * our completions.
+ *
+ * Each test gets its own scratch directory, as they run in parallel.
+ * --Elizafox
+ */
+
+#![cfg(unix)]
+
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+
+use reutils::DISPATCH_TABLE;
+
+const MANIFEST_PATH: &str = "var/lib/reutils/manifest";
+
+fn scratch(test: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("reutils-{test}-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn util(name: &str) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_reutils"));
+    command.arg0(name).stdin(Stdio::null());
+    command
+}
+
+// Big enough to take a few goes, and not the same all the way through
+#[cfg(feature = "cat")]
+fn test_data() -> Vec<u8> {
+    (0..3u32 << 20).map(|i| (i % 251) as u8).collect()
+}
+
+#[cfg(feature = "cat")]
+#[test]
+fn cat_to_file() {
+    let dir = scratch("cat-to-file");
+    let (input, output) = (dir.join("input"), dir.join("output"));
+    let data = test_data();
+    fs::write(&input, &data).unwrap();
+
+    let status = util("cat")
+        .args([&input, &input])
+        .stdout(fs::File::create(&output).unwrap())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(fs::read(&output).unwrap() == [&data[..], &data[..]].concat());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "cat")]
+#[test]
+fn cat_to_pipe() {
+    let dir = scratch("cat-to-pipe");
+    let input = dir.join("input");
+    let data = test_data();
+    fs::write(&input, &data).unwrap();
+
+    let output = util("cat").arg(&input).output().unwrap();
+    assert!(output.status.success());
+    assert!(output.stdout == data);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// procfs files say they're empty, so the kernel won't (or, on some kernels, silently can't) copy
+// them; cat has to fall back to reading them itself
+#[cfg(all(feature = "cat", target_os = "linux"))]
+#[test]
+fn cat_proc_file_to_file() {
+    let dir = scratch("cat-proc-file");
+    let output = dir.join("output");
+
+    let status = util("cat")
+        .arg("/proc/version")
+        .stdout(fs::File::create(&output).unwrap())
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let expected = fs::read("/proc/version").unwrap();
+    assert!(!expected.is_empty());
+    assert_eq!(fs::read(&output).unwrap(), expected);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+fn install(prefix: &Path, options: &[&str]) -> String {
+    let output = util("reutils")
+        .arg("--install")
+        .args(options)
+        .arg(prefix)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn installed_binary(prefix: &Path) -> PathBuf {
+    prefix.join(DISPATCH_TABLE.get("reutils").unwrap().0)
+}
+
+// Where each utility should be linked, relative to the prefix
+fn links(usr_merged: bool) -> Vec<(&'static str, PathBuf)> {
+    DISPATCH_TABLE
+        .into_iter()
+        .filter(|(util, _)| *util != "reutils")
+        .map(|(util, (path, _, _))| {
+            let merge = usr_merged && (path.starts_with("bin/") || path.starts_with("sbin/"));
+            let path = if merge {
+                Path::new("usr").join(path)
+            } else {
+                PathBuf::from(path)
+            };
+            (*util, path)
+        })
+        .collect()
+}
+
+// The manifest's entries, after checking its fingerprint is for the installed binary
+fn manifest_entries(prefix: &Path) -> Vec<String> {
+    let data = fs::read_to_string(prefix.join(MANIFEST_PATH)).unwrap();
+    let mut lines = data.lines().filter(|l| !l.starts_with('#'));
+
+    let size = fs::metadata(installed_binary(prefix)).unwrap().len();
+    let fingerprint = lines.next().unwrap();
+    let hash = fingerprint
+        .strip_prefix(&format!("fingerprint {size} "))
+        .unwrap_or_else(|| panic!("bad fingerprint line: {fingerprint}"));
+    assert!(hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit()));
+
+    lines.map(String::from).collect()
+}
+
+// Every utility is linked to the installed binary, and the manifest says exactly that
+fn check_installed(prefix: &Path, kind: &str, usr_merged: bool) {
+    let binary = installed_binary(prefix);
+    let binary_metadata = fs::metadata(&binary).unwrap();
+
+    let mut expected = vec![format!(
+        "binary {}",
+        DISPATCH_TABLE.get("reutils").unwrap().0
+    )];
+    for (util, relative) in links(usr_merged) {
+        let path = prefix.join(&relative);
+        let metadata = fs::symlink_metadata(&path).unwrap();
+        if kind == "symlink" {
+            assert!(metadata.file_type().is_symlink(), "{util} isn't a symlink");
+            assert_eq!(
+                fs::canonicalize(&path).unwrap(),
+                fs::canonicalize(&binary).unwrap()
+            );
+        } else {
+            assert_eq!(
+                metadata.ino(),
+                binary_metadata.ino(),
+                "{util} isn't a hard link"
+            );
+        }
+        expected.push(format!("{kind} {}", relative.display()));
+    }
+
+    assert_eq!(manifest_entries(prefix), expected);
+}
+
+#[test]
+fn install_symlinks() {
+    let dir = scratch("install-symlinks");
+
+    let output = install(&dir, &[]);
+    assert!(output.contains("Copying reutils binary"));
+    check_installed(&dir, "symlink", false);
+
+    // Nothing to do the second time round
+    let output = install(&dir, &[]);
+    for (util, _) in links(false) {
+        assert!(output.contains(&format!("Skipping {util} as it is already installed")));
+    }
+    check_installed(&dir, "symlink", false);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn install_dry_run() {
+    let dir = scratch("install-dry-run");
+
+    let output = install(&dir, &["--dry-run"]);
+    assert!(output.starts_with("Dry run, nothing will be changed\n"));
+    for (util, relative) in links(false) {
+        let line = format!("Installing {util} => {}\n", dir.join(relative).display());
+        assert!(output.contains(&line), "no {line:?} in {output:?}");
+    }
+    assert!(output.contains(&format!(
+        "Writing manifest {}",
+        dir.join(MANIFEST_PATH).display()
+    )));
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/* Each install copies the binary afresh, so the second one leaves the old hard links pointing at
+ * the old copy. They should be recognised from the manifest and replaced, unless something else has
+ * been put there since.
+ */
+#[cfg(feature = "cat")]
+#[test]
+fn install_hardlinks() {
+    let dir = scratch("install-hardlinks");
+
+    install(&dir, &["--hardlinks"]);
+    check_installed(&dir, "hardlink", false);
+
+    let cat = dir.join("bin/cat");
+    fs::remove_file(&cat).unwrap();
+    fs::write(&cat, "not reutils").unwrap();
+
+    let output = install(&dir, &["--hardlinks"]);
+    for (util, relative) in links(false) {
+        let path = dir.join(relative);
+        let line = if util == "cat" {
+            format!("Skipping cat as {} exists", path.display())
+        } else {
+            format!("Replacing old link {}", path.display())
+        };
+        assert!(output.contains(&line), "no {line:?} in {output:?}");
+    }
+    assert_eq!(fs::read(&cat).unwrap(), b"not reutils");
+    assert!(!manifest_entries(&dir).contains(&"hardlink bin/cat".to_string()));
+
+    install(&dir, &["--hardlinks", "--force"]);
+    check_installed(&dir, "hardlink", false);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "cat")]
+#[test]
+fn install_force() {
+    let dir = scratch("install-force");
+    let cat = dir.join("bin/cat");
+    fs::create_dir_all(cat.parent().unwrap()).unwrap();
+    fs::write(&cat, "not reutils").unwrap();
+
+    let output = install(&dir, &[]);
+    assert!(output.contains(&format!(
+        "Skipping cat as {} exists (use --force to replace it)",
+        cat.display()
+    )));
+    assert_eq!(fs::read(&cat).unwrap(), b"not reutils");
+
+    let output = install(&dir, &["--force"]);
+    assert!(output.contains(&format!("Replacing {}", cat.display())));
+    check_installed(&dir, "symlink", false);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Switching to the merged layout moves the links, and the old ones have to go
+#[cfg(feature = "cat")]
+#[test]
+fn install_usr_merged_upgrade() {
+    let dir = scratch("install-usr-merged");
+
+    install(&dir, &[]);
+    let output = install(&dir, &["--usr-merged"]);
+    check_installed(&dir, "symlink", true);
+
+    let (old, new) = (dir.join("bin/cat"), dir.join("usr/bin/cat"));
+    assert!(output.contains(&format!("Installing cat => {}", new.display())));
+    assert!(output.contains(&format!("Removing stale link {}", old.display())));
+    assert!(fs::symlink_metadata(&old).is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Generated from every utility's options, so a change to any of them can break the scripts
+#[test]
+fn completions_syntax() {
+    for (shell, check) in [("bash", "-n"), ("zsh", "-n"), ("fish", "--no-execute")] {
+        let script = util("reutils")
+            .arg(format!("--completions={shell}"))
+            .output()
+            .unwrap();
+        assert!(script.status.success());
+
+        let child = Command::new(shell)
+            .arg(check)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            // Nothing to check with
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => panic!("{shell}: {e}"),
+        };
+
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(&script.stdout)
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(
+            output.status.success(),
+            "{shell} {check}: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+}